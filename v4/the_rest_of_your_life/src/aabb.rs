@@ -89,6 +89,14 @@ impl Aabb {
         true
     }
 
+    pub fn center(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
     pub fn longest_axis(&self) -> usize {
         if self.x.size() > self.y.size() {
             return if self.x.size() > self.z.size() { 0 } else { 2 };
@@ -4,16 +4,27 @@ use crate::{
     constant_medium::ConstantMedium,
     hittable::{RotateY, Translate},
     hittable_list::HittableList,
-    material::{Dielectric, DiffuseLight, EmptyMaterial, Lambertian, Metal},
+    material::{Dielectric, DiffuseLight, EmptyMaterial, Lambertian, Metal, Toon},
     quad::{create_box, Quad},
     rtweekend::{random, random_range, Color, Point3, Vec3},
     sphere::Sphere,
-    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor},
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
     {color, point3, vec3},
 };
 use std::path::Path;
 use std::sync::Arc;
 
+// 画像が読み込めないときはマゼンタの単色で代用して描画を続ける
+fn image_texture(path: &str) -> Arc<dyn Texture> {
+    match ImageTexture::load(Path::new(path)) {
+        Ok(texture) => Arc::new(texture),
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", path, e);
+            Arc::new(SolidColor::new(color!(1, 0, 1)))
+        }
+    }
+}
+
 pub fn minimal_scene() -> (HittableList, HittableList, Camera, bool) {
     let mut world = HittableList::new();
 
@@ -256,8 +267,7 @@ pub fn two_perlin_spheres() -> (HittableList, HittableList, Camera, bool) {
 }
 
 pub fn earth() -> (HittableList, HittableList, Camera, bool) {
-    let image_path = Path::new("./data/earthmap.jpg");
-    let earth_texture = Arc::new(ImageTexture::new(image_path));
+    let earth_texture = image_texture("./data/earthmap.jpg");
     let earth_surface = Arc::new(Lambertian::new(earth_texture));
     let globe = Arc::new(Sphere::new(point3!(0, 0, 0), 2.0, earth_surface));
 
@@ -683,9 +693,7 @@ pub fn final_scene() -> (HittableList, HittableList, Camera, bool) {
         color!(1, 1, 1),
     )));
 
-    let emat = Arc::new(Lambertian::new(Arc::new(ImageTexture::new(Path::new(
-        "./data/earthmap.jpg",
-    )))));
+    let emat = Arc::new(Lambertian::new(image_texture("./data/earthmap.jpg")));
     world.add(Arc::new(Sphere::new(point3!(400, 200, 400), 100.0, emat)));
 
     let pertext = Arc::new(NoiseTexture::new(0.2));
@@ -754,3 +762,217 @@ pub fn final_scene() -> (HittableList, HittableList, Camera, bool) {
 
     (world, lights, cam, direct_light_sampling)
 }
+
+pub fn cornell_fire() -> (HittableList, HittableList, Camera, bool) {
+    // オブジェクトの設定
+    let mut world = HittableList::new();
+
+    let red = Arc::new(Lambertian::new(Arc::new(SolidColor::new(color!(
+        0.65, 0.05, 0.05
+    )))));
+    let white = Arc::new(Lambertian::new(Arc::new(SolidColor::new(color!(
+        0.73, 0.73, 0.73
+    )))));
+    let green = Arc::new(Lambertian::new(Arc::new(SolidColor::new(color!(
+        0.12, 0.45, 0.15
+    )))));
+    let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(color!(
+        2, 2, 2
+    )))));
+
+    // Cornell box sides
+    world.add(Arc::new(Quad::new(
+        point3!(555, 0, 0),
+        vec3!(0, 0, 555),
+        vec3!(0, 555, 0),
+        green,
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(0, 0, 555),
+        vec3!(0, 0, -555),
+        vec3!(0, 555, 0),
+        red,
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(0, 555, 0),
+        vec3!(555, 0, 0),
+        vec3!(0, 0, 555),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(0, 0, 555),
+        vec3!(555, 0, 0),
+        vec3!(0, 0, -555),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(555, 0, 555),
+        vec3!(-555, 0, 0),
+        vec3!(0, 555, 0),
+        white.clone(),
+    )));
+
+    // Light
+    world.add(Arc::new(Quad::new(
+        point3!(213, 554, 227),
+        vec3!(130, 0, 0),
+        vec3!(0, 0, 105),
+        light,
+    )));
+
+    // Fire ball
+    let boundary = Arc::new(Sphere::new(point3!(278, 200, 278), 150.0, white.clone()));
+    world.add(Arc::new(ConstantMedium::new_emissive(
+        boundary,
+        0.02,
+        Arc::new(SolidColor::new(color!(0.2, 0.2, 0.2))),
+        Arc::new(SolidColor::new(color!(0.08, 0.03, 0.005))),
+    )));
+
+    // ライトの設定
+    let empty_material = Arc::new(EmptyMaterial);
+    let mut lights = HittableList::new();
+    lights.add(Arc::new(Quad::new(
+        point3!(213, 554, 227),
+        vec3!(130, 0, 0),
+        vec3!(0, 0, 105),
+        empty_material.clone(),
+    )));
+    let direct_light_sampling = !lights.objects.is_empty();
+
+    // カメラの設定
+    let lookfrom = point3!(278, 278, -800);
+    let lookat = point3!(278, 278, 0);
+    let image_width = 600;
+    let aspect_ratio = 1.0;
+    let samples_per_pixel = 100;
+    let max_depth = 20;
+    let background = color!(0, 0, 0);
+    let vfov = 40.0;
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+
+    let cam = Camera::new(
+        lookfrom,
+        lookat,
+        image_width,
+        aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+        background,
+        vfov,
+        defocus_angle,
+        focus_dist,
+    );
+
+    (world, lights, cam, direct_light_sampling)
+}
+
+pub fn cornell_toon() -> (HittableList, HittableList, Camera, bool) {
+    // ライトの設定 (セルシェーディングの陰影もこのライトから決まる)
+    let empty_material = Arc::new(EmptyMaterial);
+    let mut lights = HittableList::new();
+    lights.add(Arc::new(Quad::new(
+        point3!(213, 554, 227),
+        vec3!(130, 0, 0),
+        vec3!(0, 0, 105),
+        empty_material.clone(),
+    )));
+    let direct_light_sampling = !lights.objects.is_empty();
+
+    // オブジェクトの設定
+    let mut world = HittableList::new();
+
+    let toon = |albedo: Color| Arc::new(Toon::new(Arc::new(SolidColor::new(albedo)), &lights, 3));
+    let red = toon(color!(0.65, 0.05, 0.05));
+    let white = toon(color!(0.73, 0.73, 0.73));
+    let green = toon(color!(0.12, 0.45, 0.15));
+    let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(color!(
+        1, 1, 1
+    )))));
+
+    // Cornell box sides
+    world.add(Arc::new(Quad::new(
+        point3!(555, 0, 0),
+        vec3!(0, 0, 555),
+        vec3!(0, 555, 0),
+        green,
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(0, 0, 555),
+        vec3!(0, 0, -555),
+        vec3!(0, 555, 0),
+        red,
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(0, 555, 0),
+        vec3!(555, 0, 0),
+        vec3!(0, 0, 555),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(0, 0, 555),
+        vec3!(555, 0, 0),
+        vec3!(0, 0, -555),
+        white.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        point3!(555, 0, 555),
+        vec3!(-555, 0, 0),
+        vec3!(0, 555, 0),
+        white.clone(),
+    )));
+
+    // Light
+    world.add(Arc::new(Quad::new(
+        point3!(213, 554, 227),
+        vec3!(130, 0, 0),
+        vec3!(0, 0, 105),
+        light,
+    )));
+
+    // Box
+    let box1 = Arc::new(create_box(
+        point3!(0, 0, 0),
+        point3!(165, 330, 165),
+        white.clone(),
+    ));
+    let box1 = Arc::new(RotateY::new(box1, 15.0));
+    let box1 = Arc::new(Translate::new(box1, vec3!(265, 0, 295)));
+    world.add(box1);
+
+    // Sphere
+    let sphere = Arc::new(Sphere::new(
+        point3!(190, 90, 190),
+        90.0,
+        toon(color!(0.2, 0.4, 0.9)),
+    ));
+    world.add(sphere);
+
+    // カメラの設定
+    let lookfrom = point3!(278, 278, -800);
+    let lookat = point3!(278, 278, 0);
+    let image_width = 600;
+    let aspect_ratio = 1.0;
+    let samples_per_pixel = 16;
+    let max_depth = 10;
+    let background = color!(0, 0, 0);
+    let vfov = 40.0;
+    let defocus_angle = 0.0;
+    let focus_dist = 10.0;
+
+    let cam = Camera::new(
+        lookfrom,
+        lookat,
+        image_width,
+        aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+        background,
+        vfov,
+        defocus_angle,
+        focus_dist,
+    );
+
+    (world, lights, cam, direct_light_sampling)
+}
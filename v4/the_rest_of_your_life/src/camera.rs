@@ -1,11 +1,12 @@
 use crate::{
+    film::Film,
     hittable::HitRecord,
     hittable::Hittable,
     interval::Interval,
     material::ScatterRecord,
-    pdf::{HittablePdf, MixturePdf, Pdf},
-    rtweekend::{random, Color, Point3, Ray, Vec3, INFINITY},
-    utils::write_ppm,
+    pdf::{CosinePdf, HittablePdf, MixturePdf, Pdf},
+    ray::RayDifferential,
+    rtweekend::{random, Color, Point3, Ray, Vec3, INFINITY, PI},
     vec3::random_in_unit_disk,
     {color, vec3},
 };
@@ -24,6 +25,10 @@ pub struct Camera {
     pub defocus_angle: f64,
     pub focus_dist: f64,
     pub vup: Vec3,
+    // カメラレイが何にも当たらなかったピクセルを透明にする。背景色はライティングには使われる
+    pub transparent_background: bool,
+    // 輪郭線などの後処理に使う補助バッファ (深度, 法線, オブジェクト ID) を求めるか
+    aux_buffers: bool,
 
     image_height: usize,
     pixel_samples_scale: f64,
@@ -98,6 +103,8 @@ impl Camera {
             defocus_angle,
             focus_dist,
             vup,
+            transparent_background: false,
+            aux_buffers: false,
             image_height,
             pixel_samples_scale,
             sqrt_spp,
@@ -111,7 +118,26 @@ impl Camera {
         }
     }
 
+    // ピクセルごとに 1 本余分にレイを飛ばして Film の補助バッファを埋める
+    pub fn with_aux_buffers(mut self) -> Self {
+        self.aux_buffers = true;
+        self
+    }
+
     pub fn render(&self, world: &dyn Hittable, lights: &dyn Hittable, direct_light_sampling: bool) {
+        let film = self.render_to_film(world, lights, direct_light_sampling);
+
+        eprint!("Write PPM ...");
+        film.write_ppm();
+        eprintln!(" Done.");
+    }
+
+    pub fn render_to_film(
+        &self,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        direct_light_sampling: bool,
+    ) -> Film {
         let pb = ProgressBar::new(self.image_height as u64);
         pb.set_style(
             ProgressStyle::default_bar()
@@ -123,26 +149,35 @@ impl Camera {
         );
         pb.inc(0);
 
-        let pixels = (0..self.image_height)
+        let pixels: Vec<_> = (0..self.image_height)
             .into_par_iter()
             .flat_map(|j| {
-                let mut row_data = vec![color!(0, 0, 0); self.image_width];
+                let mut row_data = Vec::with_capacity(self.image_width);
                 for i in 0..self.image_width {
                     let mut pixel_color = color!(0, 0, 0);
+                    let mut pixel_alpha = 0.0;
                     for s_j in 0..self.sqrt_spp {
                         for s_i in 0..self.sqrt_spp {
                             let r = self.get_ray(i, j, s_i, s_j);
 
-                            pixel_color += self.ray_color(
-                                r,
-                                world,
-                                lights,
-                                direct_light_sampling,
-                                self.max_depth,
-                            );
+                            let (color, alpha) =
+                                self.sample_color(r, world, lights, direct_light_sampling);
+                            pixel_color += color;
+                            pixel_alpha += alpha;
                         }
                     }
-                    row_data[i] = self.pixel_samples_scale * pixel_color;
+                    let (depth, normal, obj_id) = if self.aux_buffers {
+                        self.trace_aux(world, i, j)
+                    } else {
+                        (f64::INFINITY, Vec3::default(), 0)
+                    };
+                    row_data.push((
+                        self.pixel_samples_scale * pixel_color,
+                        self.pixel_samples_scale * pixel_alpha,
+                        depth,
+                        normal,
+                        obj_id,
+                    ));
                 }
                 pb.inc(1);
                 row_data
@@ -150,9 +185,127 @@ impl Camera {
             .collect();
         pb.finish();
 
-        eprint!("Write PPM ...");
-        write_ppm(pixels, self.image_width, self.image_height);
-        eprintln!(" Done.");
+        let mut film = Film::new(self.image_width, self.image_height);
+        for (k, (color, alpha, depth, normal, obj_id)) in pixels.into_iter().enumerate() {
+            film.color[k] = color;
+            film.alpha[k] = alpha;
+            film.depth[k] = depth;
+            film.normal[k] = normal;
+            film.obj_id[k] = obj_id;
+        }
+        film
+    }
+
+    // カメラレイ 1 本分の色とアルファ。ホールドアウトとシャドウキャッチャーは最初の交点でだけ扱う。
+    // 最初の交点は一度だけ求め、色もその交点から計算する
+    fn sample_color(
+        &self,
+        r: Ray,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        direct_light_sampling: bool,
+    ) -> (Color, f64) {
+        let mut rec = HitRecord::default();
+        if !world.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            if self.transparent_background {
+                return (color!(0, 0, 0), 0.0);
+            }
+            return (self.background, 1.0);
+        }
+
+        let mat = unsafe { &*rec.mat.expect("Material not set on hit object") };
+        if mat.is_holdout() {
+            return (color!(0, 0, 0), 0.0);
+        }
+        if mat.is_shadow_catcher() {
+            // 影の部分だけを黒の不透明度として出力する
+            let alpha = self.shadow_alpha(&r, &rec, world, lights, direct_light_sampling);
+            return (color!(0, 0, 0), alpha);
+        }
+
+        let color = self.shade(
+            r,
+            &rec,
+            world,
+            lights,
+            direct_light_sampling,
+            self.max_depth,
+        );
+        (color, 1.0)
+    }
+
+    // 遮蔽物がない場合とある場合の直接光を同じサンプルで見積もり、遮られた割合を返す。
+    // ライトと拡散反射の方向を 1 本ずつ選び、バランスヒューリスティックで混ぜる
+    fn shadow_alpha(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        direct_light_sampling: bool,
+    ) -> f64 {
+        let mut directions = vec![CosinePdf::new(rec.normal).generate()];
+        if direct_light_sampling {
+            directions.push(lights.random(&rec.p));
+        }
+
+        let mut unoccluded = 0.0;
+        let mut occluded = 0.0;
+        for direction in directions {
+            let cosine = rec.normal.dot(direction.unit());
+            if cosine <= 0.0 {
+                continue;
+            }
+            let mut pdf = cosine / PI;
+            if direct_light_sampling {
+                pdf += lights.pdf_value(&rec.p, &direction);
+            }
+
+            let (free, blocked) = self.direct_light(r.spawn(rec.p, direction), world);
+            let weight = cosine / PI / pdf;
+            unoccluded += weight * free.e.iter().sum::<f64>();
+            occluded += weight * blocked.e.iter().sum::<f64>();
+        }
+
+        if unoccluded <= 0.0 {
+            return 0.0;
+        }
+        (1.0 - occluded / unoccluded).clamp(0.0, 1.0)
+    }
+
+    // レイの先にある最初の光源 (なければ背景) の放射を、遮蔽物を無視した場合とした場合で返す
+    fn direct_light(&self, mut r: Ray, world: &dyn Hittable) -> (Color, Color) {
+        let mut blocked = false;
+        for _ in 0..self.max_depth {
+            let mut rec = HitRecord::default();
+            if !world.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rec) {
+                return split_by_occlusion(self.background, blocked);
+            }
+
+            let mat = unsafe { &*rec.mat.expect("Material not set on hit object") };
+            let emission = mat.emitted(&rec, rec.u, rec.v, &rec.p);
+            if emission.e.iter().any(|&c| c > 0.0) {
+                return split_by_occlusion(emission, blocked);
+            }
+
+            // 光らない物体は遮蔽物として通り抜ける
+            blocked = true;
+            r = r.spawn(rec.p, r.dir);
+        }
+        (color!(0, 0, 0), color!(0, 0, 0))
+    }
+
+    // 補助バッファ用に、ピクセル中心へ向かうレイの最初の交点を調べる
+    fn trace_aux(&self, world: &dyn Hittable, i: usize, j: usize) -> (f64, Vec3, usize) {
+        let pixel_center =
+            self.pixel00_loc + (i as f64 * self.pixel_delta_u) + (j as f64 * self.pixel_delta_v);
+        let r = Ray::new(self.center, pixel_center - self.center);
+
+        let mut rec = HitRecord::default();
+        if !world.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            return (f64::INFINITY, Vec3::default(), 0);
+        }
+        (rec.t * r.dir.length(), rec.geometric_normal, rec.obj_id)
     }
 
     pub fn get_ray(&self, i: usize, j: usize, s_i: u32, s_j: u32) -> Ray {
@@ -169,7 +322,17 @@ impl Camera {
         let ray_direction = pixel_sample - ray_origin;
         let ray_time = random();
 
+        // サンプル数が多いほど 1 サンプルの受け持つ範囲は狭いので、差分を縮める
+        let scale = self.recip_sqrt_spp.max(0.125);
+        let differential = RayDifferential {
+            rx_orig: ray_origin,
+            rx_dir: ray_direction + scale * self.pixel_delta_u,
+            ry_orig: ray_origin,
+            ry_dir: ray_direction + scale * self.pixel_delta_v,
+        };
+
         Ray::new_with_time(ray_origin, ray_direction, ray_time)
+            .with_differential(Some(differential))
     }
 
     fn sample_square_stratified(&self, s_i: u32, s_j: u32) -> Vec3 {
@@ -211,6 +374,23 @@ impl Camera {
             return self.background;
         }
 
+        self.shade(r, &rec, world, lights, direct_light_sampling, depth)
+    }
+
+    // 交点が分かっているレイの色
+    fn shade(
+        &self,
+        r: Ray,
+        rec: &HitRecord,
+        world: &dyn Hittable,
+        lights: &dyn Hittable,
+        direct_light_sampling: bool,
+        depth: u32,
+    ) -> Color {
+        if depth == 0 {
+            return color!(0, 0, 0);
+        }
+
         let mat_ptr = match rec.mat {
             Some(p) => p,
             None => panic!("Material not set on hit object"),
@@ -218,21 +398,25 @@ impl Camera {
         let mat = unsafe { &*mat_ptr };
 
         let mut srec = ScatterRecord::default();
-        let color_from_emission = mat.emitted(&rec, rec.u, rec.v, &rec.p);
+        let color_from_emission = mat.emitted(rec, rec.u, rec.v, &rec.p);
 
-        if !mat.scatter(&r, &rec, &mut srec) {
+        if !mat.scatter(&r, rec, &mut srec) {
             return color_from_emission;
         }
 
         if srec.skip_pdf {
-            return srec.attenuation
-                * self.ray_color(
-                    srec.skip_pdf_ray,
-                    world,
-                    lights,
-                    direct_light_sampling,
-                    depth - 1,
-                );
+            if !rec.same_side(&srec.skip_pdf_ray.dir) {
+                return color_from_emission;
+            }
+            return color_from_emission
+                + srec.attenuation
+                    * self.ray_color(
+                        srec.skip_pdf_ray,
+                        world,
+                        lights,
+                        direct_light_sampling,
+                        depth - 1,
+                    );
         }
 
         let p: Box<dyn Pdf> = if direct_light_sampling {
@@ -242,21 +426,294 @@ impl Camera {
             let light_ptr = Box::new(HittablePdf::new(lights_ptr, rec.p));
             Box::new(MixturePdf::new(
                 light_ptr,
-                srec.opt_pdf_ptr.expect("PDF not set"),
+                srec.opt_pdf_ptr.take().expect("PDF not set"),
             ))
         } else {
-            srec.opt_pdf_ptr.expect("PDF not set")
+            srec.opt_pdf_ptr.take().expect("PDF not set")
         };
 
-        let scattered = Ray::new_with_time(rec.p, p.generate(), r.time);
+        let scattered = r.spawn(rec.p, p.generate());
         let pdf_value = p.value(&scattered.dir);
+        if pdf_value <= 0.0 || !rec.same_side(&scattered.dir) {
+            return color_from_emission;
+        }
 
-        let scattering_pdf = mat.scattering_pdf(&r, &rec, &scattered);
+        let scattering_color = mat.scattering_color(&r, rec, &srec, &scattered);
 
         let sample_color =
             self.ray_color(scattered, world, lights, direct_light_sampling, depth - 1);
-        let color_from_scatter = (srec.attenuation * scattering_pdf * sample_color) / pdf_value;
+        let color_from_scatter = (scattering_color * sample_color) / pdf_value;
 
         color_from_emission + color_from_scatter
     }
 }
+
+// 遮蔽物を無視した場合とした場合の放射
+fn split_by_occlusion(radiance: Color, blocked: bool) -> (Color, Color) {
+    if blocked {
+        (radiance, color!(0, 0, 0))
+    } else {
+        (radiance, radiance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constant_medium::ConstantMedium,
+        hittable_list::HittableList,
+        material::{DiffuseLight, EmptyMaterial, Holdout, Lambertian, ShadowCatcher},
+        point3,
+        quad::Quad,
+        sphere::Sphere,
+        texture::SolidColor,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_holdout_and_shadow_catcher_alpha() {
+        let gray = Arc::new(SolidColor::new(color!(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Quad::new(
+            point3!(-20, 0, 20),
+            vec3!(40, 0, 0),
+            vec3!(0, 0, -40),
+            Arc::new(ShadowCatcher::new(gray.clone())),
+        )));
+        world.add(Arc::new(Sphere::new(
+            point3!(0, 5, 0),
+            2.0,
+            Arc::new(Lambertian::new(gray)),
+        )));
+        world.add(Arc::new(Sphere::new(
+            point3!(-10, 5, 0),
+            1.0,
+            Arc::new(Holdout),
+        )));
+        world.add(Arc::new(Quad::new(
+            point3!(-0.5, 10, -0.5),
+            vec3!(1, 0, 0),
+            vec3!(0, 0, 1),
+            Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(color!(
+                10, 10, 10
+            ))))),
+        )));
+        let mut lights = HittableList::new();
+        lights.add(Arc::new(Quad::new(
+            point3!(-0.5, 10, -0.5),
+            vec3!(1, 0, 0),
+            vec3!(0, 0, 1),
+            Arc::new(EmptyMaterial),
+        )));
+
+        let mut cam = Camera::new(
+            point3!(0, 5, -20),
+            point3!(0, 0, 0),
+            10,
+            1.0,
+            1,
+            10,
+            color!(0, 0, 0),
+            40.0,
+            0.0,
+            10.0,
+        );
+        let sample = |origin: Point3, target: Point3| {
+            cam.sample_color(Ray::new(origin, target - origin), &world, &lights, true)
+        };
+
+        let (color, alpha) = sample(point3!(-10, 5, -20), point3!(-10, 5, 0));
+        assert_eq!((color, alpha), (color!(0, 0, 0), 0.0));
+
+        // 背景は透明にしない限り不透明
+        let (_, alpha) = sample(point3!(0, 5, -20), point3!(0, 5, -40));
+        assert_eq!(alpha, 1.0);
+
+        // 球の真下は完全に影になり、離れた場所には影がない
+        let (_, alpha) = sample(point3!(0, 1, -20), point3!(0, 0, 0));
+        assert_eq!(alpha, 1.0);
+        let (_, alpha) = sample(point3!(12, 1, -20), point3!(12, 0, 0));
+        assert_eq!(alpha, 0.0);
+
+        cam.transparent_background = true;
+        let r = Ray::new(point3!(0, 5, -20), vec3!(0, 0, -1));
+        assert_eq!(
+            cam.sample_color(r, &world, &lights, true),
+            (color!(0, 0, 0), 0.0)
+        );
+    }
+
+    #[test]
+    fn test_aux_buffers_optional() {
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(
+            point3!(0, 0, 0),
+            1.0,
+            Arc::new(EmptyMaterial),
+        )));
+        let lights = HittableList::new();
+        let cam = Camera::new(
+            point3!(0, 0, -5),
+            point3!(0, 0, 0),
+            4,
+            1.0,
+            1,
+            1,
+            color!(0, 0, 0),
+            20.0,
+            0.0,
+            5.0,
+        );
+
+        let film = cam.render_to_film(&world, &lights, false);
+        assert!(film.depth.iter().all(|d| d.is_infinite()));
+        assert!(film.obj_id.iter().all(|&id| id == 0));
+
+        let film = cam
+            .with_aux_buffers()
+            .render_to_film(&world, &lights, false);
+        assert!(film.depth.iter().any(|d| d.is_finite()));
+        assert!(film.obj_id.iter().any(|&id| id != 0));
+    }
+
+    #[test]
+    fn test_shadow_catcher_background_light() {
+        // 光源はなく、背景だけで照らされる
+        let gray = Arc::new(SolidColor::new(color!(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Quad::new(
+            point3!(-20, 0, 20),
+            vec3!(40, 0, 0),
+            vec3!(0, 0, -40),
+            Arc::new(ShadowCatcher::new(gray.clone())),
+        )));
+        let lights = HittableList::new();
+
+        let cam = Camera::new(
+            point3!(0, 5, -20),
+            point3!(0, 0, 0),
+            10,
+            1.0,
+            1,
+            10,
+            color!(1, 1, 1),
+            40.0,
+            0.0,
+            10.0,
+        );
+        let origin = point3!(0, 0.5, -20);
+        let r = Ray::new(origin, point3!(0, 0, 0) - origin);
+        assert_eq!(cam.sample_color(r.clone(), &world, &lights, false).1, 0.0);
+
+        // 遮蔽物を通り抜けたレイが背景に届いても影になる
+        world.add(Arc::new(Quad::new(
+            point3!(-1000, 1, -1000),
+            vec3!(2000, 0, 0),
+            vec3!(0, 0, 2000),
+            Arc::new(Lambertian::new(gray)),
+        )));
+        for _ in 0..20 {
+            assert_eq!(cam.sample_color(r.clone(), &world, &lights, false).1, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_shadow_catcher_two_lights() {
+        // 2 つの光源のうち片方だけが遮られると、影の濃さは半分になる
+        let gray = Arc::new(SolidColor::new(color!(0.5, 0.5, 0.5)));
+        let light = Arc::new(DiffuseLight::new(Arc::new(SolidColor::new(color!(
+            10, 10, 10
+        )))));
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        world.add(Arc::new(Quad::new(
+            point3!(-20, 0, 20),
+            vec3!(40, 0, 0),
+            vec3!(0, 0, -40),
+            Arc::new(ShadowCatcher::new(gray.clone())),
+        )));
+        world.add(Arc::new(Sphere::new(
+            point3!(-2.5, 2.5, 0),
+            1.0,
+            Arc::new(Lambertian::new(gray)),
+        )));
+        for x in [-5.5, 4.5] {
+            let corner = point3!(x, 5, -0.5);
+            world.add(Arc::new(Quad::new(
+                corner,
+                vec3!(1, 0, 0),
+                vec3!(0, 0, 1),
+                light.clone(),
+            )));
+            lights.add(Arc::new(Quad::new(
+                corner,
+                vec3!(1, 0, 0),
+                vec3!(0, 0, 1),
+                Arc::new(EmptyMaterial),
+            )));
+        }
+
+        let cam = Camera::new(
+            point3!(0, 5, -20),
+            point3!(0, 0, 0),
+            10,
+            1.0,
+            1,
+            10,
+            color!(0, 0, 0),
+            40.0,
+            0.0,
+            10.0,
+        );
+        let origin = point3!(0, 0.5, -20);
+        let r = Ray::new(origin, point3!(0, 0, 0) - origin);
+        let n = 400;
+        let mean = (0..n)
+            .map(|_| cam.sample_color(r.clone(), &world, &lights, true).1)
+            .sum::<f64>()
+            / n as f64;
+        assert!((mean - 0.5).abs() < 0.15, "{}", mean);
+    }
+
+    #[test]
+    fn test_transparent_background_with_stochastic_medium() {
+        // 媒質の中で散乱するかどうかはサンプルごとに変わる
+        let background = color!(0.2, 0.4, 0.6);
+        let mut world = HittableList::new();
+        world.add(Arc::new(ConstantMedium::new_with_color(
+            Arc::new(Sphere::new(point3!(0, 0, 0), 1.0, Arc::new(EmptyMaterial))),
+            0.35,
+            color!(0.5, 0.5, 0.5),
+        )));
+        let lights = HittableList::new();
+
+        let mut cam = Camera::new(
+            point3!(0, 0, -5),
+            point3!(0, 0, 0),
+            10,
+            1.0,
+            1,
+            10,
+            background,
+            20.0,
+            0.0,
+            5.0,
+        );
+        cam.transparent_background = true;
+
+        let mut alphas = [0; 2];
+        for _ in 0..200 {
+            let r = Ray::new(point3!(0, 0, -5), vec3!(0, 0, 1));
+            let (color, alpha) = cam.sample_color(r, &world, &lights, false);
+            if alpha == 0.0 {
+                assert_eq!(color, color!(0, 0, 0));
+            } else {
+                assert_eq!(alpha, 1.0);
+                assert_ne!(color, background);
+            }
+            alphas[alpha as usize] += 1;
+        }
+        assert!(alphas[0] > 0 && alphas[1] > 0);
+    }
+}
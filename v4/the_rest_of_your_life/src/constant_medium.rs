@@ -2,7 +2,7 @@ use crate::{
     aabb::Aabb,
     hittable::{HitRecord, Hittable},
     interval::Interval,
-    material::{Isotropic, Material},
+    material::{EmissiveIsotropic, Isotropic, Material},
     rtweekend::{random, Color, Ray, Vec3, INFINITY},
     texture::Texture,
     vec3,
@@ -31,6 +31,20 @@ impl ConstantMedium {
             phase_function: Arc::new(Isotropic::new_with_color(color)),
         }
     }
+
+    // emit は単位長さあたりの放射輝度で、密度場として空間的に変化するテクスチャも使える
+    pub fn new_emissive(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        tex: Arc<dyn Texture>,
+        emit: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function: Arc::new(EmissiveIsotropic::new(tex, emit, density)),
+        }
+    }
 }
 
 impl Hittable for ConstantMedium {
@@ -76,7 +90,9 @@ impl Hittable for ConstantMedium {
         rec.p = r.at(rec.t);
 
         rec.normal = vec3!(1, 0, 0);
+        rec.geometric_normal = rec.normal;
         rec.front_face = true;
+        rec.obj_id = self as *const Self as usize;
         rec.mat = Some(std::sync::Arc::as_ptr(&self.phase_function));
 
         true
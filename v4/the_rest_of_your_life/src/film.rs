@@ -0,0 +1,200 @@
+use crate::{
+    color,
+    rtweekend::{Color, Vec3},
+    utils::{write_exr, write_png, write_ppm},
+};
+use image::ImageResult;
+use std::path::Path;
+
+// 画像に書き出すときのアルファの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Straight,
+    Premultiplied,
+}
+
+// レンダリング結果と、後処理に使う補助バッファ (深度, 法線, オブジェクト ID)
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub color: Vec<Color>,
+    // カバレッジ。color はアルファを乗算済みの値を持つ
+    pub alpha: Vec<f64>,
+    pub depth: Vec<f64>,
+    pub normal: Vec<Vec3>,
+    pub obj_id: Vec<usize>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        let n = width * height;
+        Self {
+            width,
+            height,
+            color: vec![color!(0, 0, 0); n],
+            alpha: vec![1.0; n],
+            depth: vec![f64::INFINITY; n],
+            normal: vec![Vec3::default(); n],
+            obj_id: vec![0; n],
+        }
+    }
+
+    pub fn write_ppm(&self) {
+        write_ppm(self.color.clone(), self.width, self.height);
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&self, path: P, mode: AlphaMode) -> ImageResult<()> {
+        write_png(
+            &self.color,
+            &self.alpha,
+            self.width,
+            self.height,
+            mode,
+            path,
+        )
+    }
+
+    pub fn write_exr<P: AsRef<Path>>(&self, path: P, mode: AlphaMode) -> ImageResult<()> {
+        write_exr(
+            &self.color,
+            &self.alpha,
+            self.width,
+            self.height,
+            mode,
+            path,
+        )
+    }
+}
+
+// 深度・法線・オブジェクト ID の不連続を検出して、線を描き込む後処理
+pub struct OutlinePass {
+    pub color: Color,
+    // 隣り合うピクセルの深度の相対的な差がこれを超えると線を引く
+    pub depth_threshold: f64,
+    // 法線同士の cos がこれを下回ると線を引く
+    pub normal_threshold: f64,
+}
+
+impl Default for OutlinePass {
+    fn default() -> Self {
+        Self {
+            color: color!(0, 0, 0),
+            depth_threshold: 0.1,
+            normal_threshold: 0.8,
+        }
+    }
+}
+
+impl OutlinePass {
+    fn is_discontinuous(&self, film: &Film, a: usize, b: usize) -> bool {
+        if film.obj_id[a] != film.obj_id[b] {
+            return true;
+        }
+        if film.obj_id[a] == 0 {
+            return false;
+        }
+
+        let depth_a = film.depth[a];
+        let depth_b = film.depth[b];
+        (depth_a - depth_b).abs() > self.depth_threshold * depth_a.min(depth_b)
+            || film.normal[a].dot(film.normal[b]) < self.normal_threshold
+    }
+
+    pub fn edges(&self, film: &Film) -> Vec<bool> {
+        let mut edges = vec![false; film.width * film.height];
+        for j in 0..film.height {
+            for i in 0..film.width {
+                let a = j * film.width + i;
+                if i + 1 < film.width && self.is_discontinuous(film, a, a + 1) {
+                    edges[a] = true;
+                    edges[a + 1] = true;
+                }
+                if j + 1 < film.height && self.is_discontinuous(film, a, a + film.width) {
+                    edges[a] = true;
+                    edges[a + film.width] = true;
+                }
+            }
+        }
+
+        edges
+    }
+
+    pub fn apply(&self, film: &mut Film) {
+        let edges = self.edges(film);
+        for (pixel, edge) in film.color.iter_mut().zip(edges) {
+            if edge {
+                *pixel = self.color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3;
+
+    #[test]
+    fn test_outline_edges() {
+        // 左半分と右半分で別の物体。右上の 1 ピクセルだけ左と同じ物体の別の面
+        let mut film = Film::new(4, 2);
+        for j in 0..2 {
+            for i in 0..4 {
+                let k = j * 4 + i;
+                film.obj_id[k] = if i < 2 { 1 } else { 2 };
+                film.depth[k] = 10.0;
+                film.normal[k] = vec3!(0, 0, 1);
+            }
+        }
+        film.obj_id[3] = 1;
+        film.normal[3] = vec3!(1, 0, 0);
+
+        let edges = OutlinePass::default().edges(&film);
+        assert_eq!(
+            edges,
+            vec![false, true, true, true, false, true, true, true]
+        );
+
+        // 背景同士は線にならない
+        let film = Film::new(3, 3);
+        assert!(OutlinePass::default().edges(&film).iter().all(|&e| !e));
+    }
+
+    #[test]
+    fn test_write_png_alpha() {
+        let mut film = Film::new(2, 1);
+        film.color[0] = color!(0.125, 0.125, 0.125);
+        film.alpha[0] = 0.5;
+        film.color[1] = color!(0.25, 0.25, 0.25);
+
+        let path = std::env::temp_dir().join(format!("film_test_{}.png", std::process::id()));
+        film.write_png(&path, AlphaMode::Straight).unwrap();
+        let image = image::open(&path).unwrap().to_rgba8();
+        // ストレートアルファに戻してからガンマ補正される
+        assert_eq!(image.get_pixel(0, 0).0, [128, 128, 128, 128]);
+        assert_eq!(image.get_pixel(1, 0).0, [128, 128, 128, 255]);
+
+        film.write_png(&path, AlphaMode::Premultiplied).unwrap();
+        let image = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [64, 64, 64, 128]);
+        assert_eq!(image.get_pixel(1, 0).0, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn test_write_exr_alpha() {
+        let mut film = Film::new(1, 1);
+        film.color[0] = color!(0.25, 0.5, 2.0);
+        film.alpha[0] = 0.5;
+
+        let path = std::env::temp_dir().join(format!("film_test_{}.exr", std::process::id()));
+        film.write_exr(&path, AlphaMode::Straight).unwrap();
+        let image = image::open(&path).unwrap().to_rgba32f();
+        assert_eq!(image.get_pixel(0, 0).0, [0.5, 1.0, 4.0, 0.5]);
+
+        film.write_exr(&path, AlphaMode::Premultiplied).unwrap();
+        let image = image::open(&path).unwrap().to_rgba32f();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [0.25, 0.5, 2.0, 0.5]);
+    }
+}
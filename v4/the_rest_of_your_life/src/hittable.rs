@@ -3,7 +3,9 @@ use crate::{
     interval::Interval,
     material::Material,
     point3,
-    rtweekend::{Point3, Ray, Vec3, INFINITY},
+    ray::RayDifferential,
+    rtweekend::{random, Point3, Ray, Vec3, INFINITY},
+    texture::Texture,
     vec3,
 };
 use std::sync::Arc;
@@ -11,11 +13,26 @@ use std::sync::Arc;
 #[derive(Clone, Copy)]
 pub struct HitRecord {
     pub p: Point3,
+    // シェーディング法線。バンプマップなどで摂動される
     pub normal: Vec3,
+    // 実際の面の法線。normal と同じくレイの来た側を向く
+    pub geometric_normal: Vec3,
     pub t: f64,
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // 曲面パラメータ (u, v) に対する位置の偏微分。異方性材質の接線に使う
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    // 画面上で 1 ピクセル隣に移ったときの位置と (u, v) の変化量。差分を持たないレイでは 0
+    pub dpdx: Vec3,
+    pub dpdy: Vec3,
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64,
+    // 輪郭線の検出に使う、交差したプリミティブの識別子
+    pub obj_id: usize,
     pub mat: Option<*const dyn Material>,
 }
 
@@ -24,10 +41,20 @@ impl Default for HitRecord {
         Self {
             p: Point3::default(),
             normal: Vec3::default(),
+            geometric_normal: Vec3::default(),
             t: 0.0,
             u: 0.0,
             v: 0.0,
             front_face: false,
+            dpdu: Vec3::default(),
+            dpdv: Vec3::default(),
+            dpdx: Vec3::default(),
+            dpdy: Vec3::default(),
+            dudx: 0.0,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.0,
+            obj_id: 0,
             mat: None,
         }
     }
@@ -40,10 +67,108 @@ impl HitRecord {
             *outward_normal
         } else {
             -*outward_normal
-        }
+        };
+        self.geometric_normal = self.normal;
+    }
+
+    // シェーディング法線を差し替える。視線の反対側を向いた法線は
+    // 材質が黒くなるので、視線方向の半球に入るまで曲げる
+    pub fn set_shading_normal(&mut self, r: &Ray, shading_normal: Vec3) {
+        let wo = -r.dir.unit();
+        let n = shading_normal.unit();
+        let cos = wo.dot(n);
+        self.normal = if cos < SHADING_NORMAL_EPSILON {
+            (n + (SHADING_NORMAL_EPSILON - cos) * wo).unit()
+        } else {
+            n
+        };
+    }
+
+    // 方向 dir が幾何法線とシェーディング法線の両方で同じ側にあるか。
+    // 食い違う方向へ散乱すると面の裏側へ光が漏れる
+    pub fn same_side(&self, dir: &Vec3) -> bool {
+        dir.dot(self.geometric_normal) * dir.dot(self.normal) >= 0.0
+    }
+
+    // レイの差分を交点の接平面と交差させて、dpdx, dpdy と (u, v) の変化量を求める。
+    // p, geometric_normal, dpdu, dpdv を設定した後に呼ぶ
+    pub fn compute_differentials(&mut self, r: &Ray) {
+        let n = self.geometric_normal;
+        let offset = |orig: Point3, dir: Vec3| {
+            let t = n.dot(self.p - orig) / n.dot(dir);
+            t.is_finite().then(|| orig + t * dir - self.p)
+        };
+        let (dpdx, dpdy) = r
+            .differential
+            .and_then(|diff| {
+                Some((
+                    offset(diff.rx_orig, diff.rx_dir)?,
+                    offset(diff.ry_orig, diff.ry_dir)?,
+                ))
+            })
+            .unwrap_or_default();
+
+        // dpdx = dudx dpdu + dvdx dpdv を最小二乗法で解く
+        let ata00 = self.dpdu.dot(self.dpdu);
+        let ata01 = self.dpdu.dot(self.dpdv);
+        let ata11 = self.dpdv.dot(self.dpdv);
+        let det = ata00 * ata11 - ata01 * ata01;
+        let inv_det = if det.abs() < 1e-12 { 0.0 } else { 1.0 / det };
+        let solve = |d: Vec3| {
+            let b0 = self.dpdu.dot(d);
+            let b1 = self.dpdv.dot(d);
+            (
+                (ata11 * b0 - ata01 * b1) * inv_det,
+                (ata00 * b1 - ata01 * b0) * inv_det,
+            )
+        };
+
+        self.dpdx = dpdx;
+        self.dpdy = dpdy;
+        (self.dudx, self.dvdx) = solve(dpdx);
+        (self.dudy, self.dvdy) = solve(dpdy);
+    }
+
+    // 鏡面反射した方向 dir へ差分を伝えたレイ。法線の曲率は無視する
+    pub fn spawn_reflected(&self, r_in: &Ray, dir: Vec3) -> Ray {
+        self.spawn_specular(r_in, dir, |dd, dc| dd + 2.0 * dc * self.normal)
+    }
+
+    // 相対屈折率 eta で屈折した方向 dir へ差分を伝えたレイ。法線の曲率は無視する
+    pub fn spawn_refracted(&self, r_in: &Ray, dir: Vec3, eta: f64) -> Ray {
+        let cos_i = -r_in.dir.unit().dot(self.normal);
+        let cos_t = -dir.unit().dot(self.normal);
+        self.spawn_specular(r_in, dir, |dd, dc| {
+            if cos_t.abs() < 1e-8 {
+                return Vec3::default();
+            }
+            eta * dd + (eta - eta * eta * cos_i / cos_t) * dc * self.normal
+        })
+    }
+
+    // d_dir は (入射方向の変化, 入射の cos の変化) から出射方向の変化を返す
+    fn spawn_specular(&self, r_in: &Ray, dir: Vec3, d_dir: impl Fn(Vec3, f64) -> Vec3) -> Ray {
+        let d = r_in.dir.unit();
+        let wo = dir.unit();
+        let differential = r_in.differential.map(|diff| {
+            let offset = |rd: Vec3| {
+                let dd = rd.unit() - d;
+                wo + d_dir(dd, -dd.dot(self.normal))
+            };
+            RayDifferential {
+                rx_orig: self.p + self.dpdx,
+                rx_dir: offset(diff.rx_dir),
+                ry_orig: self.p + self.dpdy,
+                ry_dir: offset(diff.ry_dir),
+            }
+        });
+
+        r_in.spawn(self.p, dir).with_differential(differential)
     }
 }
 
+const SHADING_NORMAL_EPSILON: f64 = 0.01;
+
 pub trait Hittable: Sync + Send {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self) -> Aabb;
@@ -74,7 +199,10 @@ impl Translate {
 
 impl Hittable for Translate {
     fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let offset_r = Ray::new_with_time(r.orig - self.offset, r.dir, r.time);
+        let offset_r = Ray::new_with_time(r.orig - self.offset, r.dir, r.time).with_differential(
+            r.differential
+                .map(|diff| diff.map(|orig| orig - self.offset, |dir| dir)),
+        );
         if !self.obj_ptr.hit(&offset_r, ray_t, rec) {
             return false;
         }
@@ -146,7 +274,14 @@ impl Hittable for RotateY {
         direction.e[0] = self.cos_theta * r.dir.e[0] - self.sin_theta * r.dir.e[2];
         direction.e[2] = self.sin_theta * r.dir.e[0] + self.cos_theta * r.dir.e[2];
 
-        let rotated_r = Ray::new_with_time(origin, direction, r.time);
+        let to_object = |v: Vec3| {
+            let mut rotated = v;
+            rotated.e[0] = self.cos_theta * v.e[0] - self.sin_theta * v.e[2];
+            rotated.e[2] = self.sin_theta * v.e[0] + self.cos_theta * v.e[2];
+            rotated
+        };
+        let rotated_r = Ray::new_with_time(origin, direction, r.time)
+            .with_differential(r.differential.map(|diff| diff.map(to_object, to_object)));
 
         if !self.obj_ptr.hit(&rotated_r, ray_t, rec) {
             return false;
@@ -156,12 +291,20 @@ impl Hittable for RotateY {
         p.e[0] = self.cos_theta * rec.p.e[0] + self.sin_theta * rec.p.e[2];
         p.e[2] = -self.sin_theta * rec.p.e[0] + self.cos_theta * rec.p.e[2];
 
-        let mut normal = rec.normal;
-        normal.e[0] = self.cos_theta * rec.normal.e[0] + self.sin_theta * rec.normal.e[2];
-        normal.e[2] = -self.sin_theta * rec.normal.e[0] + self.cos_theta * rec.normal.e[2];
+        let rotate = |v: Vec3| {
+            let mut rotated = v;
+            rotated.e[0] = self.cos_theta * v.e[0] + self.sin_theta * v.e[2];
+            rotated.e[2] = -self.sin_theta * v.e[0] + self.cos_theta * v.e[2];
+            rotated
+        };
 
         rec.p = p;
-        rec.normal = normal;
+        rec.normal = rotate(rec.normal);
+        rec.geometric_normal = rotate(rec.geometric_normal);
+        rec.dpdu = rotate(rec.dpdu);
+        rec.dpdv = rotate(rec.dpdv);
+        rec.dpdx = rotate(rec.dpdx);
+        rec.dpdy = rotate(rec.dpdy);
 
         true
     }
@@ -170,3 +313,193 @@ impl Hittable for RotateY {
         self.bbox
     }
 }
+
+// 不透明度テクスチャで交差を切り抜くラッパー。threshold を指定すると閾値未満を捨て、
+// 指定しなければ不透明度を確率として確率的に捨てる
+pub struct AlphaCutout {
+    obj_ptr: Arc<dyn Hittable>,
+    alpha: Arc<dyn Texture>,
+    threshold: Option<f64>,
+}
+
+impl AlphaCutout {
+    pub fn new(obj_ptr: Arc<dyn Hittable>, alpha: Arc<dyn Texture>, threshold: f64) -> Self {
+        Self {
+            obj_ptr,
+            alpha,
+            threshold: Some(threshold),
+        }
+    }
+
+    pub fn new_stochastic(obj_ptr: Arc<dyn Hittable>, alpha: Arc<dyn Texture>) -> Self {
+        Self {
+            obj_ptr,
+            alpha,
+            threshold: None,
+        }
+    }
+
+    fn is_opaque(&self, rec: &HitRecord) -> bool {
+        let alpha = self.alpha.alpha_at(rec);
+        match self.threshold {
+            Some(threshold) => alpha >= threshold,
+            None => random() < alpha,
+        }
+    }
+}
+
+// 切り抜かれた交点の先を探し直す回数の上限
+const MAX_CUTOUT_LAYERS: usize = 16;
+
+impl Hittable for AlphaCutout {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let mut ray_t = ray_t;
+        // 切り抜かれた交点で rec を上書きしないよう、一時的な記録に交差させる
+        let mut layer_rec = HitRecord::default();
+        for _ in 0..MAX_CUTOUT_LAYERS {
+            if !self.obj_ptr.hit(r, ray_t, &mut layer_rec) {
+                return false;
+            }
+            if self.is_opaque(&layer_rec) {
+                *rec = layer_rec;
+                return true;
+            }
+            ray_t = Interval::new(layer_rec.t + 1e-6, ray_t.max);
+        }
+
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.obj_ptr.bounding_box()
+    }
+
+    // 光源として選ばれる方向の分布は切り抜く前の形状のものと一致させる
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.obj_ptr.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.obj_ptr.random(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color,
+        material::EmptyMaterial,
+        quad::Quad,
+        rtweekend::{Color, INFINITY},
+        vec3::{reflect, refract},
+    };
+
+    // u < 0.5 の側だけ透明なテクスチャ
+    struct HalfAlpha;
+
+    impl Texture for HalfAlpha {
+        fn value(&self, _: f64, _: f64, _: &Point3) -> Color {
+            color!(1, 1, 1)
+        }
+
+        fn alpha(&self, u: f64, _: f64, _: &Point3) -> f64 {
+            if u < 0.5 {
+                0.0
+            } else {
+                1.0
+            }
+        }
+    }
+
+    #[test]
+    fn test_alpha_cutout() {
+        let quad = |z: f64| -> Arc<dyn Hittable> {
+            Arc::new(Quad::new(
+                point3!(0, 0, z),
+                vec3!(1, 0, 0),
+                vec3!(0, 1, 0),
+                Arc::new(EmptyMaterial),
+            ))
+        };
+        let cutout = AlphaCutout::new(quad(0.0), Arc::new(HalfAlpha), 0.5);
+        let mut rec = HitRecord::default();
+
+        let r = Ray::new(point3!(0.75, 0.5, -1), vec3!(0, 0, 1));
+        assert!(cutout.hit(&r, Interval::new(0.001, INFINITY), &mut rec));
+        assert_eq!(rec.t, 1.0);
+        let r = Ray::new(point3!(0.25, 0.5, -1), vec3!(0, 0, 1));
+        assert!(!cutout.hit(&r, Interval::new(0.001, INFINITY), &mut rec));
+
+        // 透明な部分では奥の面の交点が見つかる
+        let mut layers = crate::hittable_list::HittableList::new();
+        layers.add(quad(0.0));
+        layers.add(quad(1.0));
+        let cutout = AlphaCutout::new_stochastic(Arc::new(layers), Arc::new(HalfAlpha));
+        let r = Ray::new(point3!(0.25, 0.5, -1), vec3!(0, 0, 1));
+        assert!(!cutout.hit(&r, Interval::new(0.001, INFINITY), &mut rec));
+        let r = Ray::new(point3!(0.75, 0.5, -1), vec3!(0, 0, 1));
+        assert!(cutout.hit(&r, Interval::new(0.001, INFINITY), &mut rec));
+        assert_eq!(rec.t, 1.0);
+
+        // 手前の透明な部分は、先に記録された奥の交点を消さない
+        let mut world = crate::hittable_list::HittableList::new();
+        world.add(quad(0.0));
+        world.add(Arc::new(AlphaCutout::new(
+            quad(-0.5),
+            Arc::new(HalfAlpha),
+            0.5,
+        )));
+        let r = Ray::new(point3!(0.25, 0.5, -1), vec3!(0, 0, 1));
+        let mut rec = HitRecord::default();
+        assert!(world.hit(&r, Interval::new(0.001, INFINITY), &mut rec));
+        assert_eq!(rec.t, 1.0);
+    }
+
+    #[test]
+    fn test_ray_differentials() {
+        // 2 x 3 の長方形を斜めに見る
+        let quad = Quad::new(
+            point3!(-1, -1, 0),
+            vec3!(2, 0, 0),
+            vec3!(0, 3, 0),
+            Arc::new(EmptyMaterial),
+        );
+        let dir = vec3!(0.2, 0, 1);
+        let r = Ray::new(point3!(0, 0, -1), dir).with_differential(Some(RayDifferential {
+            rx_orig: point3!(0, 0, -1),
+            rx_dir: dir + vec3!(0.01, 0, 0),
+            ry_orig: point3!(0, 0, -1),
+            ry_dir: dir + vec3!(0, 0.01, 0),
+        }));
+        let mut rec = HitRecord::default();
+        assert!(quad.hit(&r, Interval::new(0.001, INFINITY), &mut rec));
+        assert!((rec.dpdx - vec3!(0.01, 0, 0)).length() < 1e-12);
+        assert!((rec.dudx - 0.005).abs() < 1e-12);
+        assert!(rec.dvdx.abs() < 1e-12);
+        assert!((rec.dvdy - 0.01 / 3.0).abs() < 1e-12);
+
+        // 鏡面反射・屈折した差分は、隣のレイを実際に反射・屈折させた方向と一次まで一致する
+        let rx_dir = (dir + vec3!(0.01, 0, 0)).unit();
+        let reflected = rec.spawn_reflected(&r, reflect(&dir.unit(), &rec.normal));
+        let expected = reflect(&rx_dir, &rec.normal);
+        let diff = reflected.differential.unwrap();
+        assert!((diff.rx_dir - expected).length() < 1e-4);
+        assert_eq!(diff.rx_orig, rec.p + rec.dpdx);
+
+        let eta = 1.0 / 1.5;
+        let refracted = rec.spawn_refracted(&r, refract(&dir.unit(), &rec.normal, eta), eta);
+        let expected = refract(&rx_dir, &rec.normal, eta);
+        assert!((refracted.differential.unwrap().rx_dir - expected).length() < 1e-4);
+
+        // 散乱したレイには差分を引き継がない
+        assert!(r.spawn(rec.p, dir).differential.is_none());
+        let mut rec = HitRecord::default();
+        quad.hit(
+            &Ray::new(point3!(0, 0, -1), dir),
+            Interval::new(0.001, INFINITY),
+            &mut rec,
+        );
+        assert_eq!(rec.dudx, 0.0);
+    }
+}
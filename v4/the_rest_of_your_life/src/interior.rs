@@ -0,0 +1,114 @@
+use crate::rtweekend::Color;
+
+const MAX_INTERIORS: usize = 8;
+
+// レイが現在内部にいる誘電体
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InteriorEntry {
+    // 材質ごとの識別子。同じ材質を共有する物体どうしは区別せず、重なりを 1 つの媒質として扱う
+    pub id: usize,
+    pub priority: u32,
+    pub ref_idx: f64,
+    pub absorption: Color,
+}
+
+// パスに沿って追跡する入れ子の誘電体のスタック
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InteriorStack {
+    entries: [InteriorEntry; MAX_INTERIORS],
+    len: usize,
+}
+
+impl InteriorStack {
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.entries[..self.len].iter().any(|e| e.id == id)
+    }
+
+    pub fn count(&self, id: usize) -> usize {
+        self.entries[..self.len]
+            .iter()
+            .filter(|e| e.id == id)
+            .count()
+    }
+
+    // 入れ子が深すぎる場合は追跡を諦めて捨てる
+    pub fn push(&mut self, entry: InteriorEntry) {
+        if self.len < MAX_INTERIORS {
+            self.entries[self.len] = entry;
+            self.len += 1;
+        }
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if let Some(index) = self.entries[..self.len].iter().rposition(|e| e.id == id) {
+            self.entries.copy_within(index + 1..self.len, index);
+            self.len -= 1;
+        }
+    }
+
+    // 優先度が最も高い媒質 (同じ優先度なら後から入ったもの) がその場所の媒質になる
+    pub fn top(&self) -> Option<&InteriorEntry> {
+        self.top_excluding(usize::MAX)
+    }
+
+    pub fn top_excluding(&self, id: usize) -> Option<&InteriorEntry> {
+        self.entries[..self.len].iter().filter(|e| e.id != id).fold(
+            None,
+            |top: Option<&InteriorEntry>, e| match top {
+                Some(t) if t.priority > e.priority => Some(t),
+                _ => Some(e),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: usize, priority: u32) -> InteriorEntry {
+        InteriorEntry {
+            id,
+            priority,
+            ref_idx: 1.0 + id as f64 / 10.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_top() {
+        let mut stack = InteriorStack::default();
+        assert!(stack.top().is_none());
+
+        stack.push(entry(1, 2));
+        stack.push(entry(2, 1));
+        assert_eq!(stack.top().unwrap().id, 1);
+        assert_eq!(stack.top_excluding(1).unwrap().id, 2);
+
+        stack.push(entry(3, 2));
+        assert_eq!(stack.top().unwrap().id, 3);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut stack = InteriorStack::default();
+        stack.push(entry(1, 0));
+        stack.push(entry(2, 0));
+        stack.push(entry(3, 0));
+
+        stack.remove(2);
+        assert!(!stack.contains(2));
+        assert_eq!(stack.top().unwrap().id, 3);
+
+        stack.remove(3);
+        stack.remove(1);
+        assert!(stack.is_empty());
+
+        stack.remove(1);
+        assert!(stack.is_empty());
+    }
+}
@@ -3,17 +3,27 @@ pub mod build_scene;
 pub mod bvh;
 pub mod camera;
 pub mod constant_medium;
+pub mod film;
 pub mod hittable;
 pub mod hittable_list;
+pub mod interior;
 pub mod interval;
+pub mod mapping;
 pub mod material;
+pub mod merl;
+pub mod microfacet;
+pub mod mipmap;
+pub mod normal_map;
 pub mod onb;
 pub mod pdf;
 pub mod perlin;
+pub mod principled;
+pub mod procedural;
 pub mod quad;
 pub mod ray;
 pub mod rtweekend;
 pub mod sphere;
+pub mod subsurface;
 pub mod texture;
 pub mod utils;
 pub mod vec3;
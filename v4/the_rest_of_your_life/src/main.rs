@@ -3,8 +3,8 @@ use the_rest_of_your_life::{bvh::BvhNode, hittable::Hittable};
 
 #[allow(unused_imports)]
 use the_rest_of_your_life::build_scene::{
-    cornell_box, cornell_smoke, earth, final_scene, minimal_scene, random_scene, simple_light,
-    two_perlin_spheres, two_spheres,
+    cornell_box, cornell_fire, cornell_smoke, cornell_toon, earth, final_scene, minimal_scene,
+    random_scene, simple_light, two_perlin_spheres, two_spheres,
 };
 
 fn main() {
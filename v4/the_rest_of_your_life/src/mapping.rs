@@ -0,0 +1,344 @@
+use crate::{
+    color,
+    hittable::HitRecord,
+    rtweekend::{Color, Point3, Vec3, PI},
+    sphere::Sphere,
+    texture::Texture,
+    vec3,
+};
+use std::sync::Arc;
+
+// 交点から (u, v) を作る投影
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    // 物体の持つ (u, v) をそのまま使う
+    Uv,
+    // origin を原点として u_axis, v_axis 方向の座標を取る。軸の長さが 1 マスの大きさになる
+    Planar {
+        origin: Point3,
+        u_axis: Vec3,
+        v_axis: Vec3,
+    },
+    // center から見た方向の経度と緯度。Sphere の (u, v) と同じ向き
+    Spherical {
+        center: Point3,
+    },
+    // y 軸まわりの角度と、center からの高さ
+    Cylindrical {
+        center: Point3,
+    },
+    // 法線の最も大きい成分の軸に沿って、ワールド座標を平面に投影する
+    Box,
+    // 3 つの軸の平面投影を法線の成分の sharpness 乗で混ぜる
+    Triplanar {
+        sharpness: f64,
+    },
+}
+
+// テクスチャを別の投影と (u, v) の変換で貼る
+pub struct MappedTexture {
+    texture: Arc<dyn Texture>,
+    projection: Projection,
+    offset: (f64, f64),
+    scale: (f64, f64),
+    sin_rotation: f64,
+    cos_rotation: f64,
+}
+
+impl MappedTexture {
+    pub fn new(texture: Arc<dyn Texture>, projection: Projection) -> Self {
+        Self {
+            texture,
+            projection,
+            offset: (0.0, 0.0),
+            scale: (1.0, 1.0),
+            sin_rotation: 0.0,
+            cos_rotation: 1.0,
+        }
+    }
+
+    // 変換は回転、拡大縮小、平行移動の順に掛かる
+    pub fn with_offset(mut self, u: f64, v: f64) -> Self {
+        self.offset = (u, v);
+        self
+    }
+
+    pub fn with_scale(mut self, u: f64, v: f64) -> Self {
+        self.scale = (u, v);
+        self
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        (self.sin_rotation, self.cos_rotation) = degrees.to_radians().sin_cos();
+        self
+    }
+
+    fn transform(&self, (u, v): (f64, f64)) -> (f64, f64) {
+        let ru = self.cos_rotation * u - self.sin_rotation * v;
+        let rv = self.sin_rotation * u + self.cos_rotation * v;
+
+        (
+            self.scale.0 * ru + self.offset.0,
+            self.scale.1 * rv + self.offset.1,
+        )
+    }
+
+    // 投影した (u, v)。axis は Box と Triplanar で使う平面の軸 (0: x, 1: y, 2: z)
+    fn project(&self, u: f64, v: f64, p: &Point3, axis: usize) -> (f64, f64) {
+        match self.projection {
+            Projection::Uv => (u, v),
+            Projection::Planar {
+                origin,
+                u_axis,
+                v_axis,
+            } => {
+                let d = *p - origin;
+                (
+                    d.dot(u_axis) / u_axis.length_squared(),
+                    d.dot(v_axis) / v_axis.length_squared(),
+                )
+            }
+            Projection::Spherical { center } => {
+                let (mut u, mut v) = (0.0, 0.0);
+                Sphere::get_sphere_uv(&(*p - center).unit(), &mut u, &mut v);
+                (u, v)
+            }
+            Projection::Cylindrical { center } => {
+                let d = *p - center;
+                ((f64::atan2(-d.e[2], d.e[0]) + PI) / (2.0 * PI), d.e[1])
+            }
+            Projection::Box | Projection::Triplanar { .. } => match axis {
+                0 => (p.e[2], p.e[1]),
+                1 => (p.e[0], p.e[2]),
+                _ => (p.e[0], p.e[1]),
+            },
+        }
+    }
+
+    // Box と Triplanar で各軸の平面に掛ける重み
+    fn axis_weights(&self, n: Vec3) -> [f64; 3] {
+        let a = n.e.map(f64::abs);
+        match self.projection {
+            Projection::Triplanar { sharpness } => {
+                let w = a.map(|c| c.powf(sharpness));
+                let sum = w[0] + w[1] + w[2];
+                if sum > 0.0 {
+                    w.map(|c| c / sum)
+                } else {
+                    [1.0, 0.0, 0.0]
+                }
+            }
+            _ => {
+                let axis = if a[0] >= a[1] && a[0] >= a[2] {
+                    0
+                } else if a[1] >= a[2] {
+                    1
+                } else {
+                    2
+                };
+                let mut w = [0.0; 3];
+                w[axis] = 1.0;
+                w
+            }
+        }
+    }
+
+    // 平面ごとに投影した交点でテクスチャを引き、重みで混ぜる
+    fn blend(&self, n: Vec3, lookup: impl Fn(usize) -> Color) -> Color {
+        let mut result = color!(0, 0, 0);
+        for (axis, w) in self.axis_weights(n).into_iter().enumerate() {
+            if w > 0.0 {
+                result += w * lookup(axis);
+            }
+        }
+        result
+    }
+
+    fn blend_scalar(&self, n: Vec3, lookup: impl Fn(usize) -> f64) -> f64 {
+        self.axis_weights(n)
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, w)| w > 0.0)
+            .map(|(axis, w)| w * lookup(axis))
+            .sum()
+    }
+
+    // 軸 axis の平面に投影した (u, v) とフットプリントを持つ交点
+    fn mapped_record(&self, rec: &HitRecord, axis: usize) -> HitRecord {
+        let uv = self.transform(self.project(rec.u, rec.v, &rec.p, axis));
+
+        // フットプリントは隣のピクセルの交点を投影した差で求める
+        let footprint = |dp: Vec3, du: f64, dv: f64| {
+            let neighbor =
+                self.transform(self.project(rec.u + du, rec.v + dv, &(rec.p + dp), axis));
+            uv_delta(self.projection, uv, neighbor)
+        };
+        let mut mapped = *rec;
+        (mapped.u, mapped.v) = uv;
+        (mapped.dudx, mapped.dvdx) = footprint(rec.dpdx, rec.dudx, rec.dvdx);
+        (mapped.dudy, mapped.dvdy) = footprint(rec.dpdy, rec.dudy, rec.dvdy);
+        mapped
+    }
+}
+
+// 投影後の (u, v) の変化量。球や円筒の継ぎ目をまたぐ差は反対側に折り返す
+fn uv_delta(projection: Projection, a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let mut du = b.0 - a.0;
+    if matches!(
+        projection,
+        Projection::Spherical { .. } | Projection::Cylindrical { .. }
+    ) {
+        du -= du.round();
+    }
+    (du, b.1 - a.1)
+}
+
+impl Texture for MappedTexture {
+    // 法線が分からないので、Box と Triplanar は z 軸の平面投影だけを使う。交点では value_at を使う
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.blend(vec3!(0, 0, 1), |axis| {
+            let (u, v) = self.transform(self.project(u, v, p, axis));
+            self.texture.value(u, v, p)
+        })
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.blend(rec.geometric_normal, |axis| {
+            self.texture.value_at(&self.mapped_record(rec, axis))
+        })
+    }
+
+    // value と同じく、Box と Triplanar は z 軸の平面投影だけを使う
+    fn alpha(&self, u: f64, v: f64, p: &Point3) -> f64 {
+        self.blend_scalar(vec3!(0, 0, 1), |axis| {
+            let (u, v) = self.transform(self.project(u, v, p, axis));
+            self.texture.alpha(u, v, p)
+        })
+    }
+
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        self.blend_scalar(rec.geometric_normal, |axis| {
+            self.texture.alpha_at(&self.mapped_record(rec, axis))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{point3, vec3};
+
+    // (u, v) を色として、v / 10 をアルファとして返すテクスチャ
+    struct UvColor;
+
+    impl Texture for UvColor {
+        fn value(&self, u: f64, v: f64, _: &Point3) -> Color {
+            color!(u, v, 0)
+        }
+
+        fn alpha(&self, _: f64, v: f64, _: &Point3) -> f64 {
+            v / 10.0
+        }
+    }
+
+    fn assert_near(a: Color, b: Color) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn test_projections() {
+        let p = point3!(1, 2, 3);
+
+        let planar = MappedTexture::new(
+            Arc::new(UvColor),
+            Projection::Planar {
+                origin: point3!(0, 0, 1),
+                u_axis: vec3!(2, 0, 0),
+                v_axis: vec3!(0, 0, 4),
+            },
+        );
+        assert_near(planar.value(0.0, 0.0, &p), color!(0.5, 0.5, 0));
+
+        // 球面投影は Sphere の (u, v) と一致する
+        let spherical = MappedTexture::new(Arc::new(UvColor), Projection::Spherical { center: p });
+        let q = p + vec3!(0, 0, 1);
+        assert_near(spherical.value(0.0, 0.0, &q), color!(0.25, 0.5, 0));
+
+        let cylindrical =
+            MappedTexture::new(Arc::new(UvColor), Projection::Cylindrical { center: p });
+        assert_near(
+            cylindrical.value(0.0, 0.0, &(p + vec3!(-1, 0.5, 0))),
+            color!(0, 0.5, 0),
+        );
+
+        let uv = MappedTexture::new(Arc::new(UvColor), Projection::Uv);
+        assert_near(uv.value(0.2, 0.7, &p), color!(0.2, 0.7, 0));
+    }
+
+    #[test]
+    fn test_box_and_triplanar() {
+        let mut rec = HitRecord::default();
+        rec.p = point3!(1, 2, 3);
+        rec.geometric_normal = vec3!(0, -1, 0);
+
+        let boxed = MappedTexture::new(Arc::new(UvColor), Projection::Box);
+        assert_near(boxed.value_at(&rec), color!(1, 3, 0));
+
+        // 軸に沿った法線なら Triplanar も Box と同じ
+        let triplanar =
+            MappedTexture::new(Arc::new(UvColor), Projection::Triplanar { sharpness: 4.0 });
+        assert_near(triplanar.value_at(&rec), color!(1, 3, 0));
+
+        // 斜めの法線では x 平面 (z, y) と y 平面 (x, z) が半分ずつ混ざる
+        rec.geometric_normal = vec3!(1, 1, 0).unit();
+        assert_near(triplanar.value_at(&rec), color!(2, 2.5, 0));
+    }
+
+    #[test]
+    fn test_box_without_normal() {
+        let mut rec = HitRecord::default();
+        rec.p = point3!(1, 2, 3);
+        rec.geometric_normal = vec3!(0, -1, 0);
+
+        // 交点があれば幾何法線で y 平面 (x, z) を選ぶ
+        let boxed = MappedTexture::new(Arc::new(UvColor), Projection::Box);
+        assert_eq!(boxed.alpha_at(&rec), 0.3);
+
+        // 法線が分からないときは位置によらず z 平面 (x, y) を使う
+        assert_near(boxed.value(0.0, 0.0, &rec.p), color!(1, 2, 0));
+        assert_eq!(boxed.alpha(0.0, 0.0, &rec.p), 0.2);
+        let p = point3!(5, 1, 1);
+        assert_near(boxed.value(0.0, 0.0, &p), color!(5, 1, 0));
+    }
+
+    #[test]
+    fn test_uv_transform() {
+        let mapped = MappedTexture::new(Arc::new(UvColor), Projection::Uv)
+            .with_rotation(90.0)
+            .with_scale(2.0, 3.0)
+            .with_offset(0.5, 0.25);
+        let p = point3!(0, 0, 0);
+        // (1, 0) -> 回転 (0, 1) -> 拡大 (0, 3) -> 移動 (0.5, 3.25)
+        assert_near(mapped.value(1.0, 0.0, &p), color!(0.5, 3.25, 0));
+
+        // フットプリントも同じ線形変換を受ける
+        let mut rec = HitRecord::default();
+        rec.u = 1.0;
+        rec.dudx = 0.01;
+        let uv = MappedTexture::new(Arc::new(FootprintColor), Projection::Uv).with_scale(2.0, 3.0);
+        assert_near(uv.value_at(&rec), color!(0.02, 0, 0));
+    }
+
+    // dudx, dvdx を色として返すテクスチャ
+    struct FootprintColor;
+
+    impl Texture for FootprintColor {
+        fn value(&self, _: f64, _: f64, _: &Point3) -> Color {
+            color!(0, 0, 0)
+        }
+
+        fn value_at(&self, rec: &HitRecord) -> Color {
+            color!(rec.dudx, rec.dvdx, 0)
+        }
+    }
+}
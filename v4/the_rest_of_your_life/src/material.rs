@@ -1,10 +1,17 @@
 use crate::{
     color,
     hittable::HitRecord,
-    pdf::{CosinePdf, Pdf, SpherePdf},
-    rtweekend::{random, Color, Point3, Ray, PI},
+    hittable_list::HittableList,
+    interior::InteriorEntry,
+    microfacet::{
+        charlie_d, fresnel_conductor, fresnel_dielectric, fresnel_thin_film_rgb, neubelt_v,
+        TrowbridgeReitz,
+    },
+    onb::Onb,
+    pdf::{cosine_pdf, CosinePdf, GgxReflectionPdf, Pdf, RoughDielectricPdf, SpherePdf},
+    rtweekend::{random, Color, Point3, Ray, Vec3, PI},
     texture::{SolidColor, Texture},
-    vec3::{random_unit_vector, reflect, refract},
+    vec3::{luminance, random_unit_vector, reflect, refract},
 };
 use std::sync::Arc;
 
@@ -25,9 +32,30 @@ pub trait Material: Sync + Send {
         0.0
     }
 
+    // BSDF と cos 項の積。色が散乱方向に依存しない材質では attenuation * scattering_pdf になる
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        srec.attenuation * self.scattering_pdf(r_in, rec, scattered)
+    }
+
     fn emitted(&self, _rec: &HitRecord, _u: f64, _v: f64, _p: &Point3) -> Color {
         color!(0, 0, 0)
     }
+
+    // カメラから直接見えたときにアルファを 0 にして切り抜く
+    fn is_holdout(&self) -> bool {
+        false
+    }
+
+    // カメラから直接見えたとき、ここに落ちる影だけを出力する
+    fn is_shadow_catcher(&self) -> bool {
+        false
+    }
 }
 
 pub struct Lambertian {
@@ -43,18 +71,148 @@ impl Lambertian {
 impl Material for Lambertian {
     fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         srec.skip_pdf = false;
-        srec.attenuation = self.albedo.value(rec.u, rec.v, &rec.p);
+        srec.attenuation = self.albedo.value_at(rec);
         srec.opt_pdf_ptr = Some(Box::new(CosinePdf::new(rec.normal)));
         true
     }
 
     fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
-        let cosine = rec.normal.dot(scattered.dir.unit());
-        if cosine < 0.0 {
-            0.0
+        cosine_pdf(rec, scattered)
+    }
+}
+
+// Oren-Nayar の粗い拡散反射。sigma は微小面の傾きの標準偏差 (ラジアン)
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    sigma: Arc<dyn Texture>,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Arc<dyn Texture>, sigma: Arc<dyn Texture>) -> Self {
+        Self { albedo, sigma }
+    }
+
+    pub fn new_with_sigma(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        Self {
+            albedo,
+            sigma: Arc::new(SolidColor::new(color!(sigma, sigma, sigma))),
+        }
+    }
+
+    // Lambert に掛かる係数 A + B max(0, cos(phi_i - phi_o)) sin(alpha) tan(beta)
+    fn factor(sigma: f64, normal: Vec3, wo: Vec3, wi: Vec3) -> f64 {
+        let sigma2 = sigma * sigma;
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        let cos_o = wo.dot(normal).clamp(-1.0, 1.0);
+        let cos_i = wi.dot(normal).clamp(-1.0, 1.0);
+        let sin_o = (1.0 - cos_o * cos_o).sqrt();
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        if sin_o < 1e-4 || sin_i < 1e-4 {
+            return a;
+        }
+
+        // 接平面に射影した方向同士の角度
+        let cos_phi = ((wo - cos_o * normal) / sin_o)
+            .dot((wi - cos_i * normal) / sin_i)
+            .max(0.0);
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
         } else {
-            cosine / PI
+            (sin_i, sin_o / cos_o)
+        };
+
+        a + b * cos_phi * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = false;
+        srec.attenuation = self.albedo.value_at(rec);
+        srec.opt_pdf_ptr = Some(Box::new(CosinePdf::new(rec.normal)));
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        cosine_pdf(rec, scattered)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let sigma = luminance(self.sigma.value_at(rec));
+        let factor = Self::factor(sigma, rec.normal, -r_in.dir.unit(), scattered.dir.unit());
+
+        srec.attenuation * factor * self.scattering_pdf(r_in, rec, scattered)
+    }
+}
+
+// 布地やベルベットの光沢。Charlie 分布と Neubelt の可視関数による鏡面ローブを拡散反射の上に重ねる
+pub struct Sheen {
+    base: Option<Arc<dyn Texture>>,
+    sheen: Arc<dyn Texture>,
+    alpha: f64,
+}
+
+impl Sheen {
+    pub fn new(sheen: Arc<dyn Texture>, roughness: f64) -> Self {
+        Self {
+            base: None,
+            sheen,
+            alpha: TrowbridgeReitz::roughness_to_alpha(roughness),
+        }
+    }
+
+    pub fn new_with_base(base: Arc<dyn Texture>, sheen: Arc<dyn Texture>, roughness: f64) -> Self {
+        Self {
+            base: Some(base),
+            ..Self::new(sheen, roughness)
+        }
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = false;
+        srec.attenuation = color!(1, 1, 1);
+        srec.opt_pdf_ptr = Some(Box::new(CosinePdf::new(rec.normal)));
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        cosine_pdf(rec, scattered)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let wo = -r_in.dir.unit();
+        let wi = scattered.dir.unit();
+        let cos_o = wo.dot(rec.normal);
+        let cos_i = wi.dot(rec.normal);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return color!(0, 0, 0);
         }
+
+        let cos_h = (wo + wi).unit().dot(rec.normal);
+        let sheen =
+            self.sheen.value_at(rec) * (charlie_d(cos_h, self.alpha) * neubelt_v(cos_o, cos_i));
+        let base = match &self.base {
+            Some(base) => base.value_at(rec) / PI,
+            None => color!(0, 0, 0),
+        };
+
+        (base + sheen) * cos_i
     }
 }
 
@@ -75,11 +233,7 @@ impl Metal {
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         let reflected = reflect(&r_in.dir.unit(), &rec.normal);
-        srec.skip_pdf_ray = Ray::new_with_time(
-            rec.p,
-            reflected + self.fuzz * random_unit_vector(),
-            r_in.time,
-        );
+        srec.skip_pdf_ray = rec.spawn_reflected(r_in, reflected + self.fuzz * random_unit_vector());
         srec.attenuation = self.albedo;
         srec.skip_pdf = true;
         srec.opt_pdf_ptr = None;
@@ -87,13 +241,257 @@ impl Material for Metal {
     }
 }
 
+// 表面を覆う薄膜。厚さ (nm) と屈折率をテクスチャで指定し、干渉による色づきを与える
+pub struct ThinFilm {
+    thickness: Arc<dyn Texture>,
+    ior: Arc<dyn Texture>,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: Arc<dyn Texture>, ior: Arc<dyn Texture>) -> Self {
+        Self { thickness, ior }
+    }
+
+    pub fn new_with_values(thickness: f64, ior: f64) -> Self {
+        Self {
+            thickness: Arc::new(SolidColor::new(color!(thickness, thickness, thickness))),
+            ior: Arc::new(SolidColor::new(color!(ior, ior, ior))),
+        }
+    }
+
+    // 屈折率 n_incident の側から見た、膜の下の基材 (eta + ik) の反射率
+    fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        n_incident: f64,
+        eta: Color,
+        k: Color,
+    ) -> Color {
+        let thickness = luminance(self.thickness.value_at(rec));
+        let ior = luminance(self.ior.value_at(rec));
+
+        fresnel_thin_film_rgb(cos_theta, n_incident, ior, thickness, eta, k)
+    }
+}
+
+// 代表的な金属の複素屈折率。RGB は分光データを 650nm, 550nm, 450nm で標本化したもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetalPreset {
+    Gold,
+    Silver,
+    Copper,
+    Aluminum,
+    Chromium,
+    Iron,
+    Titanium,
+}
+
+impl MetalPreset {
+    pub const ALL: [MetalPreset; 7] = [
+        MetalPreset::Gold,
+        MetalPreset::Silver,
+        MetalPreset::Copper,
+        MetalPreset::Aluminum,
+        MetalPreset::Chromium,
+        MetalPreset::Iron,
+        MetalPreset::Titanium,
+    ];
+
+    // (eta, k)。測定値なので、円周率に近い値があってもそのまま使う
+    #[allow(clippy::approx_constant)]
+    pub fn eta_k(self) -> (Color, Color) {
+        match self {
+            MetalPreset::Gold => (color!(0.166, 0.424, 1.455), color!(3.150, 2.350, 1.900)),
+            MetalPreset::Silver => (color!(0.140, 0.125, 0.135), color!(4.150, 3.340, 2.470)),
+            MetalPreset::Copper => (color!(0.214, 1.018, 1.241), color!(3.670, 2.580, 2.390)),
+            MetalPreset::Aluminum => (color!(1.490, 0.958, 0.618), color!(7.820, 6.690, 5.470)),
+            MetalPreset::Chromium => (color!(3.140, 3.180, 2.300), color!(3.310, 3.330, 3.080)),
+            MetalPreset::Iron => (color!(2.900, 2.920, 2.600), color!(3.100, 2.900, 2.800)),
+            MetalPreset::Titanium => (color!(2.740, 2.540, 2.120), color!(3.810, 3.430, 3.030)),
+        }
+    }
+}
+
+// 複素屈折率のフレネル項を持つ GGX マイクロファセット導体
+pub struct GgxConductor {
+    eta: Color,
+    k: Color,
+    distrib: TrowbridgeReitz,
+    rotation: f64,
+    thin_film: Option<ThinFilm>,
+}
+
+impl GgxConductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self::new_anisotropic(eta, k, roughness, roughness, 0.0)
+    }
+
+    // roughness_x は接線 dpdu の方向、roughness_y はそれと直交する方向の粗さ。
+    // rotation (度) だけ接線を法線まわりに回転させる
+    pub fn new_anisotropic(
+        eta: Color,
+        k: Color,
+        roughness_x: f64,
+        roughness_y: f64,
+        rotation: f64,
+    ) -> Self {
+        Self {
+            eta,
+            k,
+            distrib: TrowbridgeReitz::new(
+                TrowbridgeReitz::roughness_to_alpha(roughness_x),
+                TrowbridgeReitz::roughness_to_alpha(roughness_y),
+            ),
+            rotation: rotation.to_radians(),
+            thin_film: None,
+        }
+    }
+
+    pub fn new_preset(metal: MetalPreset, roughness: f64) -> Self {
+        let (eta, k) = metal.eta_k();
+        Self::new(eta, k, roughness)
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn fresnel(&self, rec: &HitRecord, cos_theta: f64) -> Color {
+        match &self.thin_film {
+            Some(film) => film.reflectance(rec, cos_theta, 1.0, self.eta, self.k),
+            None => fresnel_conductor(cos_theta, self.eta, self.k),
+        }
+    }
+
+    fn frame(&self, rec: &HitRecord) -> Onb {
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let tangent = self.rotation.cos() * uvw.u() + self.rotation.sin() * uvw.v();
+
+        Onb::build_from_w_and_tangent(rec.normal, tangent)
+    }
+}
+
+impl Material for GgxConductor {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let uvw = self.frame(rec);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+
+        if self.distrib.is_smooth() {
+            let reflected = reflect(&r_in.dir.unit(), &rec.normal);
+            srec.skip_pdf_ray = r_in.spawn(rec.p, reflected);
+            srec.attenuation = self.fresnel(rec, wo.e[2]);
+            srec.skip_pdf = true;
+            srec.opt_pdf_ptr = None;
+            return true;
+        }
+
+        srec.attenuation = color!(1, 1, 1);
+        srec.skip_pdf = false;
+        srec.opt_pdf_ptr = Some(Box::new(GgxReflectionPdf::new(uvw, wo, self.distrib)));
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let uvw = self.frame(rec);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+
+        GgxReflectionPdf::value_local(&self.distrib, wo, wi)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let uvw = self.frame(rec);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+        if wo.e[2] <= 0.0 || wi.e[2] <= 0.0 {
+            return color!(0, 0, 0);
+        }
+        let wm = (wo + wi).unit();
+
+        // D G F / (4 cos_o cos_i) に cos_i を掛けたもの
+        let f = self.fresnel(rec, wo.dot(wm));
+        f * (self.distrib.d(wm) * self.distrib.g(wo, wi) / (4.0 * wo.e[2]))
+    }
+}
+
+// 重なった誘電体では priority の高いものが優先され、低いものとの境界は偽の界面として通り抜ける
 pub struct Dielectric {
     ref_idx: f64,
+    absorption: Color,
+    priority: u32,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f64) -> Self {
-        Self { ref_idx }
+        Self {
+            ref_idx,
+            absorption: color!(0, 0, 0),
+            priority: 0,
+            thin_film: None,
+        }
+    }
+
+    // 内部の吸収係数 (単位長さあたり) を指定する
+    pub fn new_with_absorption(ref_idx: f64, absorption: Color) -> Self {
+        Self {
+            ref_idx,
+            absorption,
+            priority: 0,
+            thin_film: None,
+        }
+    }
+
+    // distance だけ内部を進んだときの透過率から吸収係数を求める。透過率は各成分 (0, 1) で指定する
+    pub fn new_with_transmittance(ref_idx: f64, transmittance: Color, distance: f64) -> Self {
+        assert!(distance > 0.0, "distance must be positive: {}", distance);
+        assert!(
+            transmittance.e.iter().all(|&t| t > 0.0 && t <= 1.0),
+            "transmittance must be in (0, 1]: {:?}",
+            transmittance
+        );
+        Self {
+            ref_idx,
+            absorption: Color {
+                e: transmittance.e.map(|t| -t.ln() / distance),
+            },
+            priority: 0,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    fn interior_entry(&self) -> InteriorEntry {
+        InteriorEntry {
+            id: self as *const Self as usize,
+            priority: self.priority,
+            ref_idx: self.ref_idx,
+            absorption: self.absorption,
+        }
+    }
+}
+
+// Beer-Lambert 則による透過率
+fn transmittance(absorption: Color, distance: f64) -> Color {
+    Color {
+        e: absorption.e.map(|a| (-a * distance).exp()),
     }
 }
 
@@ -101,31 +499,305 @@ impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         srec.skip_pdf = true;
         srec.opt_pdf_ptr = None;
-        srec.attenuation = color!(1, 1, 1);
+
+        let entry = self.interior_entry();
+        let mut interior = r_in.interior;
+        // 入った記録のない物体から出る場合は外側を空気とみなす
+        let inside = !rec.front_face && !interior.contains(entry.id);
+        if inside {
+            interior.push(entry);
+        }
+
+        // 直前の交点からレイが進んできた媒質による吸収
+        srec.attenuation = match interior.top() {
+            Some(medium) => transmittance(medium.absorption, rec.t * r_in.dir.length()),
+            None => color!(1, 1, 1),
+        };
+
+        // 同じ材質の別の物体の内側にある境界は、同じ媒質どうしの界面になる
+        let same_medium = if rec.front_face {
+            interior.contains(entry.id)
+        } else {
+            interior.count(entry.id) > 1
+        };
+        let outside = interior.top_excluding(entry.id);
+        if same_medium || outside.is_some_and(|m| m.priority > self.priority) {
+            // 偽の界面なので屈折させずにそのまま通り抜ける
+            if rec.front_face {
+                interior.push(entry);
+            } else {
+                interior.remove(entry.id);
+            }
+            srec.skip_pdf_ray = rec.spawn_refracted(r_in, r_in.dir, 1.0);
+            srec.skip_pdf_ray.interior = interior;
+            return true;
+        }
+
+        let outside_ref_idx = outside.map_or(1.0, |m| m.ref_idx);
         let etai_over_etat = if rec.front_face {
-            1.0 / self.ref_idx
+            outside_ref_idx / self.ref_idx
         } else {
-            self.ref_idx
+            self.ref_idx / outside_ref_idx
         };
 
         let unit_direction = r_in.dir.unit();
         let cos_theta = -unit_direction.dot(rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        if etai_over_etat * sin_theta > 1.0 {
+        let cannot_refract = etai_over_etat * sin_theta > 1.0;
+        let reflect_ray = match &self.thin_film {
+            Some(film) if !cannot_refract => {
+                // 反射率は色ごとに違うので、平均で選んで重みを補正する
+                let (n_incident, n_transmitted) = if rec.front_face {
+                    (outside_ref_idx, self.ref_idx)
+                } else {
+                    (self.ref_idx, outside_ref_idx)
+                };
+                let r = film.reflectance(
+                    rec,
+                    cos_theta,
+                    n_incident,
+                    color!(n_transmitted, n_transmitted, n_transmitted),
+                    color!(0, 0, 0),
+                );
+                let p = (r.e[0] + r.e[1] + r.e[2]) / 3.0;
+                if random() < p {
+                    srec.attenuation = srec.attenuation * r / p;
+                    true
+                } else {
+                    srec.attenuation = srec.attenuation * (color!(1, 1, 1) - r) / (1.0 - p);
+                    false
+                }
+            }
+            _ => cannot_refract || random() < schlick(cos_theta, etai_over_etat),
+        };
+        if reflect_ray {
             let reflected = reflect(&unit_direction, &rec.normal);
-            srec.skip_pdf_ray = Ray::new_with_time(rec.p, reflected, r_in.time);
+            srec.skip_pdf_ray = rec.spawn_reflected(r_in, reflected);
+            srec.skip_pdf_ray.interior = interior;
             return true;
         }
-        let reflect_prob = schlick(cos_theta, etai_over_etat);
-        if random() < reflect_prob {
-            let reflected = reflect(&unit_direction, &rec.normal);
-            srec.skip_pdf_ray = Ray::new_with_time(rec.p, reflected, r_in.time);
-            return true;
+
+        if rec.front_face {
+            interior.push(entry);
+        } else {
+            interior.remove(entry.id);
         }
         let refracted = refract(&unit_direction, &rec.normal, etai_over_etat);
-        srec.skip_pdf_ray = Ray::new_with_time(rec.p, refracted, r_in.time);
+        srec.skip_pdf_ray = rec.spawn_refracted(r_in, refracted, etai_over_etat);
+        srec.skip_pdf_ray.interior = interior;
+        true
+    }
+}
+
+// GGX マイクロファセットで反射と透過をする粗い誘電体
+pub struct RoughDielectric {
+    ref_idx: f64,
+    distrib: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(ref_idx: f64, roughness: f64) -> Self {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            ref_idx,
+            distrib: TrowbridgeReitz::new(alpha, alpha),
+        }
+    }
+
+    fn relative_eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ref_idx
+        } else {
+            1.0 / self.ref_idx
+        }
+    }
+
+    // 局所座標での BSDF の値 (cos 項は含まない)
+    pub fn bsdf_local(distrib: &TrowbridgeReitz, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        let wm = match RoughDielectricPdf::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+
+        let r = fresnel_dielectric(wo.dot(wm), eta);
+        let dg = distrib.d(wm) * distrib.g(wo, wi);
+        if wo.e[2] * wi.e[2] > 0.0 {
+            dg * r / (4.0 * wo.e[2] * wi.e[2]).abs()
+        } else {
+            // 放射輝度は屈折で eta^2 倍に圧縮される
+            let denom = (wi.dot(wm) + wo.dot(wm) / eta).powi(2) * wi.e[2] * wo.e[2];
+            dg * (1.0 - r) * (wi.dot(wm) * wo.dot(wm) / denom).abs() / (eta * eta)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let eta = self.relative_eta(rec);
+        srec.attenuation = color!(1, 1, 1);
+
+        if self.distrib.is_smooth() {
+            let unit_direction = r_in.dir.unit();
+            let cos_theta = -unit_direction.dot(rec.normal).min(1.0);
+            let direction = if random() < fresnel_dielectric(cos_theta, eta) {
+                reflect(&unit_direction, &rec.normal)
+            } else {
+                refract(&unit_direction, &rec.normal, 1.0 / eta)
+            };
+            srec.skip_pdf_ray = r_in.spawn(rec.p, direction);
+            srec.skip_pdf = true;
+            srec.opt_pdf_ptr = None;
+            return true;
+        }
+
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        srec.skip_pdf = false;
+        srec.opt_pdf_ptr = Some(Box::new(RoughDielectricPdf::new(
+            uvw,
+            wo,
+            eta,
+            self.distrib,
+        )));
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+
+        RoughDielectricPdf::value_local(&self.distrib, wo, wi, self.relative_eta(rec))
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+        let f = Self::bsdf_local(&self.distrib, wo, wi, self.relative_eta(rec));
+
+        color!(f, f, f) * wi.e[2].abs()
+    }
+}
+
+// 任意の材質を誘電体のコートで覆った層状の材質。フレネル反射率に従って確率的にどちらかの層を選ぶ
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ref_idx: f64,
+    distrib: TrowbridgeReitz,
+    absorption: Color,
+    thickness: f64,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, ref_idx: f64, roughness: f64) -> Self {
+        let alpha = TrowbridgeReitz::roughness_to_alpha(roughness);
+        Self {
+            base,
+            ref_idx,
+            distrib: TrowbridgeReitz::new(alpha, alpha),
+            absorption: color!(0, 0, 0),
+            thickness: 0.0,
+        }
+    }
+
+    // コートの吸収係数と厚さ
+    pub fn with_thickness(mut self, absorption: Color, thickness: f64) -> Self {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    // 下地の層に届いた光の重み。入射側の (1 - F) は層の選択確率と打ち消し合う
+    fn base_weight(&self, cos_o: f64, cos_i: f64) -> Color {
+        let cos_inside =
+            |cos: f64| (1.0 - (1.0 - cos * cos) / (self.ref_idx * self.ref_idx)).sqrt();
+        let distance = self.thickness * (1.0 / cos_inside(cos_o) + 1.0 / cos_inside(cos_i));
+
+        (1.0 - fresnel_dielectric(cos_i, self.ref_idx)) * transmittance(self.absorption, distance)
+    }
+
+    fn scatter_coat(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let unit_direction = r_in.dir.unit();
+        srec.skip_pdf = true;
+        srec.opt_pdf_ptr = None;
+
+        if self.distrib.is_smooth() {
+            srec.attenuation = color!(1, 1, 1);
+            srec.skip_pdf_ray = r_in.spawn(rec.p, reflect(&unit_direction, &rec.normal));
+            return true;
+        }
+
+        // 可視法線をサンプリングし、選択確率で割った重みを付ける
+        let uvw = Onb::build_from_w(rec.normal);
+        let wo = uvw.inverse_transform_vec3(-unit_direction);
+        let wm = self.distrib.sample_wm(wo, random(), random());
+        let wi = reflect(&-wo, &wm);
+        if wi.e[2] <= 0.0 {
+            return false;
+        }
+
+        let f = fresnel_dielectric(wo.dot(wm), self.ref_idx)
+            / fresnel_dielectric(wo.e[2], self.ref_idx)
+            * self.distrib.g(wo, wi)
+            / self.distrib.g1(wo);
+        srec.attenuation = color!(f, f, f);
+        srec.skip_pdf_ray = r_in.spawn(rec.p, uvw.transform_vec3(wi));
+        true
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec, srec);
+        }
+
+        let cos_o = -r_in.dir.unit().dot(rec.normal).min(1.0);
+        if random() < fresnel_dielectric(cos_o, self.ref_idx) {
+            return self.scatter_coat(r_in, rec, srec);
+        }
+
+        if !self.base.scatter(r_in, rec, srec) {
+            return false;
+        }
+        if srec.skip_pdf {
+            let cos_i = srec.skip_pdf_ray.dir.unit().dot(rec.normal).abs();
+            srec.attenuation = srec.attenuation * self.base_weight(cos_o, cos_i);
+        }
         true
     }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.base.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let color = self.base.scattering_color(r_in, rec, srec, scattered);
+        if !rec.front_face {
+            return color;
+        }
+
+        let cos_o = -r_in.dir.unit().dot(rec.normal).min(1.0);
+        let cos_i = scattered.dir.unit().dot(rec.normal).abs();
+        color * self.base_weight(cos_o, cos_i)
+    }
+
+    fn emitted(&self, rec: &HitRecord, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(rec, u, v, p)
+    }
 }
 
 pub struct DiffuseLight {
@@ -139,15 +811,148 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn emitted(&self, rec: &HitRecord, u: f64, v: f64, p: &Point3) -> Color {
+    fn emitted(&self, rec: &HitRecord, _u: f64, _v: f64, _p: &Point3) -> Color {
         if rec.front_face {
-            self.emit.value(u, v, p)
+            self.emit.value_at(rec)
         } else {
             color!(0, 0, 0)
         }
     }
 }
 
+// 任意の材質に放射を加える。散乱は base に任せる
+pub struct Emissive {
+    base: Arc<dyn Material>,
+    emit: Arc<dyn Texture>,
+    two_sided: bool,
+}
+
+impl Emissive {
+    // 表側だけが光る
+    pub fn new(base: Arc<dyn Material>, emit: Arc<dyn Texture>) -> Self {
+        Self {
+            base,
+            emit,
+            two_sided: false,
+        }
+    }
+
+    pub fn new_two_sided(base: Arc<dyn Material>, emit: Arc<dyn Texture>) -> Self {
+        Self {
+            base,
+            emit,
+            two_sided: true,
+        }
+    }
+}
+
+impl Material for Emissive {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        self.base.scatter(r_in, rec, srec)
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.base.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        self.base.scattering_color(r_in, rec, srec, scattered)
+    }
+
+    fn emitted(&self, rec: &HitRecord, u: f64, v: f64, p: &Point3) -> Color {
+        let base = self.base.emitted(rec, u, v, p);
+        if rec.front_face || self.two_sided {
+            base + self.emit.value_at(rec)
+        } else {
+            base
+        }
+    }
+}
+
+// セルシェーディング。ライトの中心へ向かう方向との cos を bands 段階に量子化して塗る
+pub struct Toon {
+    albedo: Arc<dyn Texture>,
+    light_centers: Vec<Point3>,
+    bands: u32,
+    ambient: f64,
+}
+
+impl Toon {
+    pub fn new(albedo: Arc<dyn Texture>, lights: &HittableList, bands: u32) -> Self {
+        Self {
+            albedo,
+            light_centers: lights
+                .objects
+                .iter()
+                .map(|light| light.bounding_box().center())
+                .collect(),
+            bands: bands.max(1),
+            ambient: 0.2,
+        }
+    }
+
+    fn shade(&self, p: &Point3, normal: Vec3) -> f64 {
+        let intensity = self
+            .light_centers
+            .iter()
+            .map(|center| (*center - *p).unit().dot(normal).max(0.0))
+            .sum::<f64>()
+            .min(1.0);
+        let level = (intensity * self.bands as f64).ceil() / self.bands as f64;
+
+        self.ambient + (1.0 - self.ambient) * level
+    }
+}
+
+impl Material for Toon {
+    fn emitted(&self, rec: &HitRecord, _u: f64, _v: f64, p: &Point3) -> Color {
+        self.albedo.value_at(rec) * self.shade(p, rec.normal)
+    }
+}
+
+// 合成用に画像を切り抜く材質。反射や屈折越しには黒く見える
+pub struct Holdout;
+
+impl Material for Holdout {
+    fn is_holdout(&self) -> bool {
+        true
+    }
+}
+
+// 合成用に影だけを受け止める材質。間接光に対しては Lambertian として振る舞う
+pub struct ShadowCatcher {
+    albedo: Arc<dyn Texture>,
+}
+
+impl ShadowCatcher {
+    pub fn new(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Material for ShadowCatcher {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = false;
+        srec.attenuation = self.albedo.value_at(rec);
+        srec.opt_pdf_ptr = Some(Box::new(CosinePdf::new(rec.normal)));
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        cosine_pdf(rec, scattered)
+    }
+
+    fn is_shadow_catcher(&self) -> bool {
+        true
+    }
+}
+
 fn schlick(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     return r0 + (1.0 - r0) * (1.0 - cosine).powi(5);
@@ -172,7 +977,37 @@ impl Isotropic {
 impl Material for Isotropic {
     fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
         srec.opt_pdf_ptr = Some(Box::new(SpherePdf));
-        srec.attenuation = self.tex.value(rec.u, rec.v, &rec.p);
+        srec.attenuation = self.tex.value_at(rec);
+
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+// 媒質内の衝突点で散乱しつつ単位長さあたりの放射を返す位相関数
+pub struct EmissiveIsotropic {
+    tex: Arc<dyn Texture>,
+    emit: Arc<dyn Texture>,
+    mean_free_path: f64,
+}
+
+impl EmissiveIsotropic {
+    pub fn new(tex: Arc<dyn Texture>, emit: Arc<dyn Texture>, density: f64) -> Self {
+        Self {
+            tex,
+            emit,
+            mean_free_path: 1.0 / density,
+        }
+    }
+}
+
+impl Material for EmissiveIsotropic {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.opt_pdf_ptr = Some(Box::new(SpherePdf));
+        srec.attenuation = self.tex.value_at(rec);
 
         true
     }
@@ -180,7 +1015,241 @@ impl Material for Isotropic {
     fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
         1.0 / (4.0 * PI)
     }
+
+    // 衝突推定量: 衝突点の放射に平均自由行程を掛けると区間に沿った放射の積分の期待値になる
+    fn emitted(&self, rec: &HitRecord, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.mean_free_path * self.emit.value_at(rec)
+    }
 }
 
 pub struct EmptyMaterial;
 impl Material for EmptyMaterial {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{point3, rtweekend::Vec3, vec3};
+
+    #[test]
+    fn test_dielectric_transmittance() {
+        let transmittance = color!(0.9, 0.5, 0.1);
+        let glass = Dielectric::new_with_transmittance(1.5, transmittance, 2.0);
+
+        let r_in = Ray::new(point3!(0, 0, 0), vec3!(0, 0, 0.5));
+        let mut rec = HitRecord::default();
+        rec.t = 4.0;
+        rec.p = r_in.at(rec.t);
+        rec.normal = vec3!(0, 0, -1);
+        rec.front_face = false;
+
+        let mut srec = ScatterRecord::default();
+        assert!(glass.scatter(&r_in, &rec, &mut srec));
+        for (result, expected) in srec.attenuation.e.iter().zip(transmittance.e) {
+            assert!((result - expected).abs() < 1e-12);
+        }
+
+        rec.front_face = true;
+        rec.normal = vec3!(0, 0, 1);
+        assert!(glass.scatter(&r_in, &rec, &mut srec));
+        assert_eq!(srec.attenuation, color!(1, 1, 1));
+    }
+
+    #[test]
+    fn test_dielectric_transmittance_clear_channel() {
+        // 透過率 1 のチャンネルは吸収しない
+        let glass = Dielectric::new_with_transmittance(1.5, color!(0.9, 1.0, 0.5), 2.0);
+
+        let r_in = Ray::new(point3!(0, 0, 0), vec3!(0, 0, 0.5));
+        let mut rec = HitRecord::default();
+        rec.t = 4.0;
+        rec.p = r_in.at(rec.t);
+        rec.normal = vec3!(0, 0, -1);
+        rec.front_face = false;
+
+        let mut srec = ScatterRecord::default();
+        assert!(glass.scatter(&r_in, &rec, &mut srec));
+        assert_eq!(srec.attenuation.e[1], 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_dielectric_transmittance_out_of_range() {
+        Dielectric::new_with_transmittance(1.5, color!(0.9, 1.5, 0.5), 2.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_dielectric_transmittance_zero_distance() {
+        Dielectric::new_with_transmittance(1.5, color!(0.9, 0.5, 0.5), 0.0);
+    }
+
+    #[test]
+    fn test_dielectric_shared_material_overlap() {
+        // 同じ材質の 2 つの球が重なっている場合、重なりの中の境界では屈折しない
+        let glass = Dielectric::new(1.5);
+        let mut r_in = Ray::new(point3!(0, 0, 0), vec3!(1, 0.5, 0));
+        r_in.interior.push(glass.interior_entry());
+        let mut rec = HitRecord::default();
+        rec.t = 1.0;
+        rec.p = r_in.at(rec.t);
+        rec.normal = vec3!(-1, 0, 0);
+        rec.front_face = true;
+
+        let mut srec = ScatterRecord::default();
+        assert!(glass.scatter(&r_in, &rec, &mut srec));
+        assert_eq!(srec.skip_pdf_ray.dir, r_in.dir);
+        assert_eq!(
+            srec.skip_pdf_ray.interior.count(glass.interior_entry().id),
+            2
+        );
+
+        // 片方から出ても、もう片方の内側なので通り抜ける
+        let r_in = srec.skip_pdf_ray.clone();
+        rec.front_face = false;
+        assert!(glass.scatter(&r_in, &rec, &mut srec));
+        assert_eq!(srec.skip_pdf_ray.dir, r_in.dir);
+        assert_eq!(
+            srec.skip_pdf_ray.interior.count(glass.interior_entry().id),
+            1
+        );
+
+        // 両方から出るときは空気との界面なので、反射か屈折で向きが変わる
+        let r_in = srec.skip_pdf_ray.clone();
+        assert!(glass.scatter(&r_in, &rec, &mut srec));
+        assert!((srec.skip_pdf_ray.dir.unit() - r_in.dir.unit()).length() > 0.1);
+    }
+
+    #[test]
+    fn test_dielectric_false_interface() {
+        let glass = Dielectric::new(1.5).with_priority(1);
+        let water = Dielectric::new(1.33);
+
+        // ガラスの内部から水に入る境界は偽の界面になる
+        let mut r_in = Ray::new(point3!(0, 0, 0), vec3!(1, 1, 0));
+        r_in.interior.push(glass.interior_entry());
+        let mut rec = HitRecord::default();
+        rec.t = 1.0;
+        rec.p = r_in.at(rec.t);
+        rec.normal = vec3!(-1, 0, 0);
+        rec.front_face = true;
+
+        let mut srec = ScatterRecord::default();
+        assert!(water.scatter(&r_in, &rec, &mut srec));
+        assert_eq!(srec.skip_pdf_ray.dir, r_in.dir);
+        assert!(srec
+            .skip_pdf_ray
+            .interior
+            .contains(water.interior_entry().id));
+        assert_eq!(
+            srec.skip_pdf_ray.interior.top().unwrap().id,
+            glass.interior_entry().id
+        );
+    }
+
+    #[test]
+    fn test_metal_preset_reflectance() {
+        for metal in MetalPreset::ALL {
+            let (eta, k) = metal.eta_k();
+            let normal = fresnel_conductor(1.0, eta, k);
+            for c in 0..3 {
+                assert!(normal.e[c] > 0.3 && normal.e[c] < 1.0, "{:?}", metal);
+            }
+        }
+
+        // 金と銅は赤く、銀はほぼ無彩色
+        let (eta, k) = MetalPreset::Gold.eta_k();
+        let gold = fresnel_conductor(1.0, eta, k);
+        assert!(gold.e[0] > gold.e[1] && gold.e[1] > gold.e[2]);
+        // 斜めから見ると白に近づく
+        let gold_grazing = fresnel_conductor(0.1, eta, k);
+        assert!(gold_grazing.e[0] - gold_grazing.e[2] < gold.e[0] - gold.e[2]);
+        let (eta, k) = MetalPreset::Copper.eta_k();
+        let copper = fresnel_conductor(1.0, eta, k);
+        assert!(copper.e[0] > copper.e[1] && copper.e[0] > copper.e[2]);
+        let (eta, k) = MetalPreset::Silver.eta_k();
+        let silver = fresnel_conductor(1.0, eta, k);
+        assert!((silver.e[0] - silver.e[2]).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_emissive_sides() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(Arc::new(SolidColor::new(color!(
+            0.5, 0.5, 0.5
+        )))));
+        let emit = Arc::new(SolidColor::new(color!(1, 2, 3)));
+        let one_sided = Emissive::new(base.clone(), emit.clone());
+        let two_sided = Emissive::new_two_sided(base, emit);
+
+        let mut rec = HitRecord::default();
+        rec.front_face = true;
+        assert_eq!(one_sided.emitted(&rec, 0.0, 0.0, &rec.p), color!(1, 2, 3));
+        rec.front_face = false;
+        assert_eq!(one_sided.emitted(&rec, 0.0, 0.0, &rec.p), color!(0, 0, 0));
+        assert_eq!(two_sided.emitted(&rec, 0.0, 0.0, &rec.p), color!(1, 2, 3));
+
+        // 散乱は下地の材質のまま
+        let r_in = Ray::new(point3!(0, 0, 1), vec3!(0, 0, -1));
+        let mut srec = ScatterRecord::default();
+        assert!(two_sided.scatter(&r_in, &rec, &mut srec));
+        assert_eq!(srec.attenuation, color!(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn test_toon_bands() {
+        let mut lights = HittableList::new();
+        lights.add(Arc::new(crate::sphere::Sphere::new(
+            point3!(0, 10, 0),
+            1.0,
+            Arc::new(EmptyMaterial),
+        )));
+        let toon = Toon::new(Arc::new(SolidColor::new(color!(1, 1, 1))), &lights, 4);
+
+        // 法線とライトの角度に応じて 4 段階のどれかになる
+        let test_cases = vec![
+            (vec3!(0, 1, 0), 1.0),
+            (vec3!(1, 1, 0).unit(), 0.2 + 0.8 * 0.75),
+            (vec3!(1, 0.1, 0).unit(), 0.2 + 0.8 * 0.25),
+            (vec3!(0, -1, 0), 0.2),
+        ];
+        for (normal, expected) in test_cases {
+            let result = toon.shade(&point3!(0, 0, 0), normal);
+            assert!(
+                (result - expected).abs() < 1e-12,
+                "Failed for input: '{:?}",
+                normal
+            );
+        }
+    }
+
+    #[test]
+    fn test_oren_nayar_factor() {
+        let normal = vec3!(0, 0, 1);
+        let wo = vec3!(0.6, 0, 0.8);
+
+        // sigma = 0 なら Lambert に一致する
+        assert_eq!(OrenNayar::factor(0.0, normal, wo, vec3!(-0.8, 0, 0.6)), 1.0);
+
+        // 逆反射方向で明るく、鏡面側では A だけになる
+        let sigma: f64 = 0.5;
+        let a = 1.0 - sigma * sigma / (2.0 * (sigma * sigma + 0.33));
+        let back = OrenNayar::factor(sigma, normal, wo, vec3!(0.8, 0, 0.6));
+        let forward = OrenNayar::factor(sigma, normal, wo, vec3!(-0.8, 0, 0.6));
+        assert!(back > a);
+        assert!((forward - a).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_coated_base_weight() {
+        let base: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Arc::new(SolidColor::new(color!(1, 1, 1)))));
+        let clear = Coated::new(base.clone(), 1.5, 0.0);
+        let tinted = Coated::new(base, 1.5, 0.0).with_thickness(color!(1, 2, 0), 0.5);
+
+        // 垂直入射ではコートを往復する距離は厚さの 2 倍
+        let result = clear.base_weight(1.0, 1.0);
+        assert!((result - color!(0.96, 0.96, 0.96)).length() < 1e-12);
+        let result = tinted.base_weight(1.0, 1.0);
+        let expected = 0.96 * color!((-1.0f64).exp(), (-2.0f64).exp(), 1);
+        assert!((result - expected).length() < 1e-12);
+    }
+}
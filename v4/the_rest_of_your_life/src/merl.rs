@@ -0,0 +1,228 @@
+use crate::{
+    color,
+    hittable::HitRecord,
+    material::{Material, ScatterRecord},
+    onb::Onb,
+    pdf::{cosine_pdf, CosinePdf},
+    rtweekend::{Color, Ray, Vec3, PI},
+    vec3,
+};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+const THETA_H_RES: usize = 90;
+const THETA_D_RES: usize = 90;
+const PHI_D_RES: usize = 180;
+const TABLE_SIZE: usize = THETA_H_RES * THETA_D_RES * PHI_D_RES;
+const CHANNEL_SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+// MERL の測定 BRDF。半角 (theta_h) と差分角 (theta_d, phi_d) で引く等方性のテーブル
+pub struct Merl {
+    table: Vec<f64>,
+}
+
+impl Merl {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    // 先頭に 3 つの i32 で次元、続いて R, G, B の順に f64 のテーブル (リトルエンディアン)
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut dims = [0; 3];
+        for dim in dims.iter_mut() {
+            let mut buf = [0u8; 4];
+            reader.read_exact(&mut buf)?;
+            *dim = i32::from_le_bytes(buf);
+        }
+        if dims != [THETA_H_RES as i32, THETA_D_RES as i32, PHI_D_RES as i32] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected MERL dimensions: {:?}", dims),
+            ));
+        }
+
+        let mut bytes = vec![0u8; 3 * TABLE_SIZE * 8];
+        reader.read_exact(&mut bytes)?;
+        let table = bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+
+        Ok(Self { table })
+    }
+
+    // 局所座標での BRDF の値
+    pub fn eval_local(&self, wo: Vec3, wi: Vec3) -> Color {
+        if wo.e[2] <= 0.0 || wi.e[2] <= 0.0 {
+            return color!(0, 0, 0);
+        }
+
+        let (theta_h, theta_d, phi_d) = half_diff_angles(wo, wi);
+        let index = phi_d_index(phi_d)
+            + theta_d_index(theta_d) * PHI_D_RES
+            + theta_h_index(theta_h) * PHI_D_RES * THETA_D_RES;
+
+        Color {
+            e: [0, 1, 2].map(|c| (self.table[index + c * TABLE_SIZE] * CHANNEL_SCALE[c]).max(0.0)),
+        }
+    }
+}
+
+// ベクトル v を軸 axis まわりに angle だけ回転させる
+fn rotate_vector(v: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+    let (sin, cos) = angle.sin_cos();
+    v * cos + axis * (axis.dot(v) * (1.0 - cos)) + axis.cross(v) * sin
+}
+
+// (wo, wi) を Rusinkiewicz の半角・差分角に変換する
+fn half_diff_angles(wo: Vec3, wi: Vec3) -> (f64, f64, f64) {
+    let half = (wo + wi).unit();
+    let theta_h = half.e[2].clamp(-1.0, 1.0).acos();
+    let phi_h = half.e[1].atan2(half.e[0]);
+
+    let diff = rotate_vector(
+        rotate_vector(wi, vec3!(0, 0, 1), -phi_h),
+        vec3!(0, 1, 0),
+        -theta_h,
+    );
+    let theta_d = diff.e[2].clamp(-1.0, 1.0).acos();
+    let phi_d = diff.e[1].atan2(diff.e[0]);
+
+    (theta_h, theta_d, phi_d)
+}
+
+// theta_h は鏡面付近を細かく取るために平方根で標本化されている
+fn theta_h_index(theta_h: f64) -> usize {
+    let index = (theta_h / (PI / 2.0)).max(0.0).sqrt() * THETA_H_RES as f64;
+    (index as usize).min(THETA_H_RES - 1)
+}
+
+fn theta_d_index(theta_d: f64) -> usize {
+    let index = theta_d / (PI / 2.0) * THETA_D_RES as f64;
+    (index.max(0.0) as usize).min(THETA_D_RES - 1)
+}
+
+// 相反性により phi_d は [0, pi) に畳み込まれている
+fn phi_d_index(phi_d: f64) -> usize {
+    let phi_d = if phi_d < 0.0 { phi_d + PI } else { phi_d };
+    let index = phi_d / PI * PHI_D_RES as f64;
+    (index.max(0.0) as usize).min(PHI_D_RES - 1)
+}
+
+impl Material for Merl {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = false;
+        srec.attenuation = color!(1, 1, 1);
+        srec.opt_pdf_ptr = Some(Box::new(CosinePdf::new(rec.normal)));
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        cosine_pdf(rec, scattered)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+
+        self.eval_local(wo, wi) * wi.e[2]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // value(theta_h_index) を全チャンネルに入れた合成データ
+    fn synthetic(value: impl Fn(usize) -> f64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for dim in [THETA_H_RES, THETA_D_RES, PHI_D_RES] {
+            bytes.extend_from_slice(&(dim as i32).to_le_bytes());
+        }
+        for _ in 0..3 {
+            for i in 0..TABLE_SIZE {
+                bytes.extend_from_slice(&value(i / (PHI_D_RES * THETA_D_RES)).to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_merl_load_and_lookup() {
+        let bytes = synthetic(|theta_h_index| theta_h_index as f64 * 1500.0);
+        let merl = Merl::from_reader(Cursor::new(bytes)).unwrap();
+
+        // 鏡面方向では theta_h = 0
+        let wo = vec3!(0.6, 0, 0.8);
+        let result = merl.eval_local(wo, vec3!(-0.6, 0, 0.8));
+        assert_eq!(result.e[0], 0.0);
+
+        let test_cases: Vec<f64> = vec![0.2, 0.7, 1.2];
+        for theta in test_cases {
+            let w = vec3!(theta.sin(), 0, theta.cos());
+            let expected = theta_h_index(theta) as f64;
+            let result = merl.eval_local(w, w);
+            assert!(
+                (result.e[0] - expected).abs() < 1e-9,
+                "Failed for input: '{}",
+                theta
+            );
+            assert!((result.e[1] - 1.15 * expected).abs() < 1e-9);
+            assert!((result.e[2] - 1.66 * expected).abs() < 1e-9);
+        }
+
+        assert_eq!(merl.eval_local(wo, vec3!(0, 0, -1)), color!(0, 0, 0));
+    }
+
+    #[test]
+    fn test_merl_invalid_file() {
+        let mut bytes = Vec::new();
+        for dim in [90i32, 90, 90] {
+            bytes.extend_from_slice(&dim.to_le_bytes());
+        }
+        let err = Merl::from_reader(bytes.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // ヘッダーの途中で終わっている
+        let err = Merl::from_reader(Cursor::new(&bytes[..6])).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // ヘッダーは正しいがテーブルが足りない
+        let mut bytes = Vec::new();
+        for dim in [THETA_H_RES, THETA_D_RES, PHI_D_RES] {
+            bytes.extend_from_slice(&(dim as i32).to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0u8; 8 * 100]);
+        let err = Merl::from_reader(Cursor::new(bytes)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        assert!(Merl::load(std::env::temp_dir().join("merl_test_missing.binary")).is_err());
+    }
+
+    #[test]
+    fn test_merl_load_file() {
+        let path = std::env::temp_dir().join(format!("merl_test_{}.binary", std::process::id()));
+        std::fs::write(
+            &path,
+            synthetic(|theta_h_index| theta_h_index as f64 * 1500.0),
+        )
+        .unwrap();
+        let result = Merl::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let merl = result.unwrap();
+        let w = vec3!(0.7f64.sin(), 0, 0.7f64.cos());
+        assert!((merl.eval_local(w, w).e[0] - theta_h_index(0.7) as f64).abs() < 1e-9);
+    }
+}
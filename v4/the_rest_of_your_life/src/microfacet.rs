@@ -0,0 +1,474 @@
+use crate::{
+    rtweekend::{Color, Vec3, PI},
+    vec3,
+};
+use std::ops::{Add, Div, Mul, Sub};
+
+// 法線を z 軸とする局所座標系での Trowbridge-Reitz (GGX) 分布
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4),
+        }
+    }
+
+    pub fn roughness_to_alpha(roughness: f64) -> f64 {
+        roughness.clamp(0.0, 1.0).powi(2)
+    }
+
+    // 十分に滑らかなら鏡面として扱う
+    pub fn is_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let cos2_theta = wm.e[2] * wm.e[2];
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let e = ((wm.e[0] / self.alpha_x).powi(2) + (wm.e[1] / self.alpha_y).powi(2)) / cos2_theta;
+
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2_theta * cos2_theta * (1.0 + e).powi(2))
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let cos2_theta = w.e[2] * w.e[2];
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let alpha2_tan2_theta =
+            ((w.e[0] * self.alpha_x).powi(2) + (w.e[1] * self.alpha_y).powi(2)) / cos2_theta;
+
+        ((1.0 + alpha2_tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Smith の高さ相関マスキング・シャドウイング関数
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // 方向 w から見える法線の分布
+    pub fn d_visible(&self, w: Vec3, wm: Vec3) -> f64 {
+        if w.e[2] == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.e[2].abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    // 可視法線分布のサンプリング (Heitz 2018)
+    pub fn sample_wm(&self, w: Vec3, u1: f64, u2: f64) -> Vec3 {
+        let w = if w.e[2] < 0.0 { -w } else { w };
+        let vh = vec3!(self.alpha_x * w.e[0], self.alpha_y * w.e[1], w.e[2]).unit();
+
+        let lensq = vh.e[0] * vh.e[0] + vh.e[1] * vh.e[1];
+        let t1 = if lensq > 0.0 {
+            vec3!(-vh.e[1], vh.e[0], 0) / lensq.sqrt()
+        } else {
+            vec3!(1, 0, 0)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.e[2]);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        vec3!(
+            self.alpha_x * nh.e[0],
+            self.alpha_y * nh.e[1],
+            nh.e[2].max(1e-6)
+        )
+        .unit()
+    }
+}
+
+// Disney のクリアコートで使う GTR1 分布
+pub fn gtr1_d(cos_theta_m: f64, alpha: f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_theta_m * cos_theta_m;
+
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+// D(wm) cos(theta_m) に比例して微小面の法線をサンプリングする
+pub fn gtr1_sample(alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+
+    vec3!(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+// 布地の光沢に使う Charlie 分布 (Estevez & Kulla 2017)
+pub fn charlie_d(cos_theta_m: f64, alpha: f64) -> f64 {
+    let inv_alpha = 1.0 / alpha.max(1e-3);
+    let sin_theta = (1.0 - cos_theta_m * cos_theta_m).max(0.0).sqrt();
+
+    (2.0 + inv_alpha) * sin_theta.powf(inv_alpha) / (2.0 * PI)
+}
+
+// Neubelt & Pettineo の可視関数 (マスキングと 1 / (4 cos_o cos_i) をまとめたもの)
+pub fn neubelt_v(cos_theta_o: f64, cos_theta_i: f64) -> f64 {
+    1.0 / (4.0 * (cos_theta_i + cos_theta_o - cos_theta_i * cos_theta_o))
+}
+
+pub fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// 相対屈折率 eta (透過側 / 入射側) の誘電体のフレネル反射率
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+// 複素屈折率 eta + ik の導体のフレネル反射率
+pub fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        fresnel_complex(cos_theta_i, eta.e[0], k.e[0]),
+        fresnel_complex(cos_theta_i, eta.e[1], k.e[1]),
+        fresnel_complex(cos_theta_i, eta.e[2], k.e[2]),
+    )
+}
+
+// RGB の各チャンネルを代表する波長 (nm)
+pub const RGB_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+// 薄膜の干渉計算に使う複素数
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm2(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // 虚部が非負になる平方根
+    fn sqrt(self) -> Self {
+        let r = self.norm2().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    // exp(i z)
+    fn exp_i(self) -> Self {
+        let scale = (-self.im).exp();
+        Self::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        let d = rhs.norm2();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / d,
+            (self.im * rhs.re - self.re * rhs.im) / d,
+        )
+    }
+}
+
+// 屈折率 n_i の媒質から n_j の媒質へ入るときの s 偏光と p 偏光の振幅反射率
+fn fresnel_amplitudes(n_i: Complex, cos_i: Complex, n_j: Complex, cos_j: Complex) -> [Complex; 2] {
+    let rs = (n_i * cos_i - n_j * cos_j) / (n_i * cos_i + n_j * cos_j);
+    let rp = (n_j * cos_i - n_i * cos_j) / (n_j * cos_i + n_i * cos_j);
+    [rs, rp]
+}
+
+// 屈折率 film_ior, 厚さ thickness (nm) の薄膜で覆われた基材 (eta + ik) の反射率。
+// 膜の中の多重反射を Airy の式で足し合わせる
+pub fn fresnel_thin_film(
+    cos_theta_i: f64,
+    n_incident: f64,
+    film_ior: f64,
+    thickness: f64,
+    eta: f64,
+    k: f64,
+    wavelength: f64,
+) -> f64 {
+    let one = Complex::new(1.0, 0.0);
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+
+    let n0 = Complex::new(n_incident, 0.0);
+    let n1 = Complex::new(film_ior, 0.0);
+    let n2 = Complex::new(eta, k);
+    // Snell の法則 n0 sin0 = n1 sin1 = n2 sin2
+    let invariant = Complex::new(n_incident * n_incident * sin2_theta_i, 0.0);
+    let cos0 = Complex::new(cos_theta_i, 0.0);
+    let cos1 = (one - invariant / (n1 * n1)).sqrt();
+    let cos2 = (one - invariant / (n2 * n2)).sqrt();
+
+    let phase = Complex::new(4.0 * PI * thickness / wavelength, 0.0) * n1 * cos1;
+    let e = phase.exp_i();
+
+    let r01 = fresnel_amplitudes(n0, cos0, n1, cos1);
+    let r12 = fresnel_amplitudes(n1, cos1, n2, cos2);
+    let reflectance = |i: usize| ((r01[i] + r12[i] * e) / (one + r01[i] * r12[i] * e)).norm2();
+
+    (0.5 * (reflectance(0) + reflectance(1))).min(1.0)
+}
+
+pub fn fresnel_thin_film_rgb(
+    cos_theta_i: f64,
+    n_incident: f64,
+    film_ior: f64,
+    thickness: f64,
+    eta: Color,
+    k: Color,
+) -> Color {
+    Color {
+        e: [0, 1, 2].map(|c| {
+            fresnel_thin_film(
+                cos_theta_i,
+                n_incident,
+                film_ior,
+                thickness,
+                eta.e[c],
+                k.e[c],
+                RGB_WAVELENGTHS[c],
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresnel_dielectric() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(-1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+        assert_eq!(fresnel_dielectric(1.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn test_fresnel_complex_normal_incidence() {
+        let test_cases = vec![(0.2, 3.9), (1.5, 0.0), (2.9, 3.0)];
+
+        for (eta, k) in test_cases {
+            let expected =
+                ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+            let result = fresnel_complex(1.0, eta, k);
+            assert!(
+                (result - expected).abs() < 1e-12,
+                "Failed for input: '{:?}",
+                (eta, k)
+            );
+        }
+    }
+
+    #[test]
+    fn test_fresnel_complex_grazing() {
+        assert!((fresnel_complex(0.0, 0.2, 3.9) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_d_normalized() {
+        // 射影面積で積分すると 1 になる
+        let test_cases = vec![(0.3, 0.3), (0.5, 0.1)];
+        let n = 400;
+
+        for (alpha_x, alpha_y) in test_cases {
+            let distrib = TrowbridgeReitz::new(alpha_x, alpha_y);
+            let mut sum = 0.0;
+            for i in 0..n {
+                let theta = (i as f64 + 0.5) / n as f64 * PI / 2.0;
+                for j in 0..n {
+                    let phi = (j as f64 + 0.5) / n as f64 * 2.0 * PI;
+                    let wm = vec3!(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        theta.cos()
+                    );
+                    sum += distrib.d(wm) * theta.cos() * theta.sin();
+                }
+            }
+            let integral = sum * (PI / 2.0 / n as f64) * (2.0 * PI / n as f64);
+            assert!(
+                (integral - 1.0).abs() < 1e-2,
+                "Failed for input: '{:?}",
+                (alpha_x, alpha_y)
+            );
+        }
+    }
+
+    #[test]
+    fn test_gtr1_d_normalized() {
+        let test_cases = vec![0.01, 0.1, 0.5];
+        let n = 20000;
+
+        for alpha in test_cases {
+            let mut sum = 0.0;
+            for i in 0..n {
+                let theta = (i as f64 + 0.5) / n as f64 * PI / 2.0;
+                sum += gtr1_d(theta.cos(), alpha) * theta.cos() * theta.sin();
+            }
+            let integral = sum * (PI / 2.0 / n as f64) * 2.0 * PI;
+            assert!(
+                (integral - 1.0).abs() < 1e-2,
+                "Failed for input: '{}",
+                alpha
+            );
+        }
+    }
+
+    #[test]
+    fn test_charlie_d_normalized() {
+        let test_cases = vec![0.1, 0.5, 1.0];
+        let n = 20000;
+
+        for alpha in test_cases {
+            let mut sum = 0.0;
+            for i in 0..n {
+                let theta = (i as f64 + 0.5) / n as f64 * PI / 2.0;
+                sum += charlie_d(theta.cos(), alpha) * theta.cos() * theta.sin();
+            }
+            let integral = sum * (PI / 2.0 / n as f64) * 2.0 * PI;
+            assert!(
+                (integral - 1.0).abs() < 1e-2,
+                "Failed for input: '{}",
+                alpha
+            );
+        }
+    }
+
+    #[test]
+    fn test_sample_wm_upper_hemisphere() {
+        let distrib = TrowbridgeReitz::new(0.5, 0.2);
+        let w = vec3!(0.6, 0.0, 0.8);
+
+        for i in 0..10 {
+            for j in 0..10 {
+                let wm = distrib.sample_wm(w, i as f64 / 10.0, j as f64 / 10.0);
+                assert!((wm.length() - 1.0).abs() < 1e-12);
+                assert!(wm.e[2] > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fresnel_thin_film_without_film() {
+        // 厚さ 0 の膜や基材と同じ屈折率の膜は無いのと同じ
+        let test_cases = vec![(1.0, 1.5, 0.0), (0.3, 1.5, 0.0), (0.7, 0.2, 3.9)];
+
+        for (cos, eta, k) in test_cases {
+            let expected = fresnel_complex(cos, eta, k);
+            let zero_thickness = fresnel_thin_film(cos, 1.0, 1.33, 0.0, eta, k, 550.0);
+            assert!(
+                (zero_thickness - expected).abs() < 1e-9,
+                "Failed for input: '{:?}",
+                (cos, eta, k)
+            );
+            if k == 0.0 {
+                let same_ior = fresnel_thin_film(cos, 1.0, eta, 300.0, eta, k, 550.0);
+                assert!(
+                    (same_ior - expected).abs() < 1e-9,
+                    "Failed for input: '{:?}",
+                    (cos, eta, k)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fresnel_thin_film_quarter_wave() {
+        // 1/4 波長の反射防止膜 (n = sqrt(1.5)) は垂直入射で反射をほぼ打ち消す
+        let film_ior = 1.5f64.sqrt();
+        let thickness = 550.0 / (4.0 * film_ior);
+        let r = fresnel_thin_film(1.0, 1.0, film_ior, thickness, 1.5, 0.0, 550.0);
+        assert!(r < 1e-9);
+        let r = fresnel_thin_film(1.0, 1.0, film_ior, thickness, 1.5, 0.0, 450.0);
+        assert!(r > 1e-4 && r < 0.04);
+    }
+}
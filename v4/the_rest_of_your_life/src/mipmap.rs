@@ -0,0 +1,382 @@
+use image::Rgba32FImage;
+
+// 範囲外のテクセル座標の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    Clamp,
+}
+
+impl WrapMode {
+    fn apply(self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            WrapMode::Repeat => i.rem_euclid(n),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m >= n {
+                    2 * n - 1 - m
+                } else {
+                    m
+                }
+            }
+            WrapMode::Clamp => i.clamp(0, n - 1),
+        };
+        i as usize
+    }
+}
+
+// テクスチャの補間方法。Trilinear と Ewa はフットプリントに応じてミップマップを使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    Nearest,
+    Bilinear,
+    Bicubic,
+    Trilinear,
+    Ewa,
+}
+
+// EWA で許す楕円の縦横比の上限
+const MAX_ANISOTROPY: f64 = 8.0;
+
+struct Level {
+    width: usize,
+    height: usize,
+    data: Vec<[f32; 4]>,
+}
+
+impl Level {
+    fn texel(&self, i: i64, j: i64, wrap: WrapMode) -> [f64; 4] {
+        let i = wrap.apply(i, self.width);
+        let j = wrap.apply(j, self.height);
+        self.data[j * self.width + i].map(|c| c as f64)
+    }
+
+    // 縦横を半分にした次のレベル。奇数の大きさでは端の 1 列 (行) を最後のテクセルにまとめる
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        // 出力テクセル i が受け持つ元のテクセルの範囲
+        let span = |i: usize, out: usize, src: usize| {
+            let end = if i + 1 == out { src } else { 2 * i + 2 };
+            2 * i..end
+        };
+        let mut data = Vec::with_capacity(width * height);
+        for j in 0..height {
+            for i in 0..width {
+                let mut sum = [0.0; 4];
+                let mut count = 0.0;
+                for y in span(j, height, self.height) {
+                    for x in span(i, width, self.width) {
+                        let t = self.data[y * self.width + x];
+                        for c in 0..4 {
+                            sum[c] += t[c] as f64;
+                        }
+                        count += 1.0;
+                    }
+                }
+                data.push(sum.map(|c| (c / count) as f32));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+// テクスチャのミップマップ。座標 (s, t) は左上を原点とする [0, 1] の範囲
+pub struct MipMap {
+    levels: Vec<Level>,
+    wrap: WrapMode,
+}
+
+impl MipMap {
+    pub fn new(image: &Rgba32FImage, wrap: WrapMode) -> Self {
+        let mut levels = vec![Level {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            data: image.pixels().map(|p| p.0).collect(),
+        }];
+        while {
+            let last = levels.last().unwrap();
+            last.width > 1 || last.height > 1
+        } {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+
+        Self { levels, wrap }
+    }
+
+    // 縮小は端の扱いに依らないので、ピラミッドは作り直さない
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level_size(&self, level: usize) -> (usize, usize) {
+        (self.levels[level].width, self.levels[level].height)
+    }
+
+    pub fn nearest(&self, s: f64, t: f64) -> [f64; 4] {
+        let level = &self.levels[0];
+        let i = (s * level.width as f64).floor() as i64;
+        let j = (t * level.height as f64).floor() as i64;
+        level.texel(i, j, self.wrap)
+    }
+
+    pub fn bilinear(&self, level: usize, s: f64, t: f64) -> [f64; 4] {
+        let level = &self.levels[level.min(self.levels.len() - 1)];
+        let x = s * level.width as f64 - 0.5;
+        let y = t * level.height as f64 - 0.5;
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+
+        let mut result = [0.0; 4];
+        for (di, dj, w) in [
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let texel = level.texel(i + di, j + dj, self.wrap);
+            for c in 0..4 {
+                result[c] += w * texel[c];
+            }
+        }
+
+        result
+    }
+
+    // Catmull-Rom スプラインによる 4x4 の補間。オーバーシュートした負の値は 0 に切り詰める
+    pub fn bicubic(&self, s: f64, t: f64) -> [f64; 4] {
+        let level = &self.levels[0];
+        let x = s * level.width as f64 - 0.5;
+        let y = t * level.height as f64 - 0.5;
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+        let wx = catmull_rom_weights(x - x.floor());
+        let wy = catmull_rom_weights(y - y.floor());
+
+        let mut result = [0.0; 4];
+        for (dj, wy) in wy.iter().enumerate() {
+            for (di, wx) in wx.iter().enumerate() {
+                let texel = level.texel(i + di as i64 - 1, j + dj as i64 - 1, self.wrap);
+                for c in 0..4 {
+                    result[c] += wx * wy * texel[c];
+                }
+            }
+        }
+
+        result.map(|c| c.max(0.0))
+    }
+
+    // フットプリントの幅 width ([0, 1] の座標単位) に合うレベルを選び、隣り合うレベル間を補間する
+    pub fn trilinear(&self, s: f64, t: f64, width: f64) -> [f64; 4] {
+        let level = self.level_for_width(width);
+        if level <= 0.0 {
+            return self.bilinear(0, s, t);
+        }
+        let last = self.levels.len() - 1;
+        if level >= last as f64 {
+            return self.bilinear(last, s, t);
+        }
+
+        let i = level.floor() as usize;
+        let delta = level - i as f64;
+        lerp(delta, self.bilinear(i, s, t), self.bilinear(i + 1, s, t))
+    }
+
+    // 楕円の重み付き平均 (Heckbert の EWA)。dst0, dst1 は画面の x, y 方向の (s, t) の変化量
+    pub fn ewa(&self, s: f64, t: f64, dst0: [f64; 2], dst1: [f64; 2]) -> [f64; 4] {
+        let (mut major, mut minor) = (dst0, dst1);
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let major_length = length(major);
+        let mut minor_length = length(minor);
+        if minor_length == 0.0 {
+            return self.bilinear(0, s, t);
+        }
+
+        // 細長すぎる楕円は短軸を伸ばしてテクセル数を抑える
+        if minor_length * MAX_ANISOTROPY < major_length {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            minor = minor.map(|c| c * scale);
+            minor_length *= scale;
+        }
+
+        let level = self.level_for_width(minor_length).max(0.0);
+        let i = level.floor() as usize;
+        if i >= self.levels.len() - 1 {
+            return self.bilinear(self.levels.len() - 1, s, t);
+        }
+        let delta = level - i as f64;
+        lerp(
+            delta,
+            self.ewa_level(i, s, t, major, minor),
+            self.ewa_level(i + 1, s, t, major, minor),
+        )
+    }
+
+    fn ewa_level(&self, level: usize, s: f64, t: f64, dst0: [f64; 2], dst1: [f64; 2]) -> [f64; 4] {
+        let lv = &self.levels[level];
+        let (w, h) = (lv.width as f64, lv.height as f64);
+        let x = s * w - 0.5;
+        let y = t * h - 0.5;
+        let dst0 = [dst0[0] * w, dst0[1] * h];
+        let dst1 = [dst1[0] * w, dst1[1] * h];
+
+        // 楕円 a s^2 + b s t + c t^2 < 1 の係数
+        let mut a = dst0[1] * dst0[1] + dst1[1] * dst1[1] + 1.0;
+        let mut b = -2.0 * (dst0[0] * dst0[1] + dst1[0] * dst1[1]);
+        let mut c = dst0[0] * dst0[0] + dst1[0] * dst1[0] + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let u_sqrt = (det * c).sqrt();
+        let v_sqrt = (a * det).sqrt();
+        let s0 = (x - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1 = (x + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0 = (y - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1 = (y + 2.0 * inv_det * v_sqrt).floor() as i64;
+
+        let mut sum = [0.0; 4];
+        let mut sum_weights = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f64 - y;
+            for is in s0..=s1 {
+                let ss = is as f64 - x;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    // ガウス関数の裾を 0 に揃えた重み
+                    let weight = (-2.0 * r2).exp() - (-2.0f64).exp();
+                    let texel = lv.texel(is, it, self.wrap);
+                    for ch in 0..4 {
+                        sum[ch] += weight * texel[ch];
+                    }
+                    sum_weights += weight;
+                }
+            }
+        }
+        if sum_weights <= 0.0 {
+            return self.bilinear(level, s, t);
+        }
+
+        sum.map(|c| c / sum_weights)
+    }
+
+    // 幅 width のフットプリントがちょうど 1 テクセルになる (連続的な) レベル
+    fn level_for_width(&self, width: f64) -> f64 {
+        let (w, h) = self.level_size(0);
+        (width.max(1e-8) * w.max(h) as f64).log2()
+    }
+}
+
+fn catmull_rom_weights(x: f64) -> [f64; 4] {
+    let x2 = x * x;
+    let x3 = x2 * x;
+    [
+        0.5 * (-x3 + 2.0 * x2 - x),
+        0.5 * (3.0 * x3 - 5.0 * x2 + 2.0),
+        0.5 * (-3.0 * x3 + 4.0 * x2 + x),
+        0.5 * (x3 - x2),
+    ]
+}
+
+fn length(v: [f64; 2]) -> f64 {
+    (v[0] * v[0] + v[1] * v[1]).sqrt()
+}
+
+fn lerp(t: f64, a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [0, 1, 2, 3].map(|c| (1.0 - t) * a[c] + t * b[c])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    // 値が x + 4 y の 4x4 画像
+    fn ramp() -> Rgba32FImage {
+        Rgba32FImage::from_fn(4, 4, |x, y| {
+            let v = (x + 4 * y) as f32;
+            Rgba([v, v, v, 1.0])
+        })
+    }
+
+    #[test]
+    fn test_wrap_modes() {
+        assert_eq!(WrapMode::Repeat.apply(-1, 4), 3);
+        assert_eq!(WrapMode::Repeat.apply(5, 4), 1);
+        assert_eq!(WrapMode::Mirror.apply(-1, 4), 0);
+        assert_eq!(WrapMode::Mirror.apply(4, 4), 3);
+        assert_eq!(WrapMode::Mirror.apply(9, 4), 1);
+        assert_eq!(WrapMode::Clamp.apply(-3, 4), 0);
+        assert_eq!(WrapMode::Clamp.apply(7, 4), 3);
+
+        // 端の扱いは後から変えられる
+        let mip = MipMap::new(&ramp(), WrapMode::Clamp);
+        assert_eq!(mip.nearest(-0.1, 0.0)[0], 0.0);
+        let mip = mip.with_wrap(WrapMode::Repeat);
+        assert_eq!(mip.nearest(-0.1, 0.0)[0], 3.0);
+    }
+
+    #[test]
+    fn test_mip_pyramid() {
+        let mip = MipMap::new(&ramp(), WrapMode::Clamp);
+        assert_eq!(mip.levels(), 3);
+        assert_eq!(mip.level_size(1), (2, 2));
+        assert_eq!(mip.level_size(2), (1, 1));
+        // 最上位のレベルは全体の平均
+        assert_eq!(mip.bilinear(2, 0.3, 0.8)[0], 7.5);
+
+        // 奇数の大きさでも 1x1 まで作られる
+        let odd = Rgba32FImage::from_pixel(5, 3, Rgba([1.0, 1.0, 1.0, 1.0]));
+        let mip = MipMap::new(&odd, WrapMode::Repeat);
+        assert_eq!(mip.level_size(mip.levels() - 1), (1, 1));
+
+        // 奇数の端のテクセルも捨てずに平均に含める
+        let mut row = Rgba32FImage::new(3, 1);
+        for (i, v) in [0.0, 3.0, 9.0].into_iter().enumerate() {
+            row.put_pixel(i as u32, 0, Rgba([v, v, v, 1.0]));
+        }
+        let mip = MipMap::new(&row, WrapMode::Repeat);
+        assert_eq!(mip.level_size(1), (1, 1));
+        assert_eq!(mip.bilinear(1, 0.5, 0.5)[0], 4.0);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mip = MipMap::new(&ramp(), WrapMode::Clamp);
+        assert_eq!(mip.nearest(0.3, 0.3)[0], 5.0);
+        // テクセル (1, 1) と (2, 1) の中間
+        assert!((mip.bilinear(0, 0.5, 0.375)[0] - 5.5).abs() < 1e-12);
+        // 1 次関数は Catmull-Rom で正確に再現される
+        assert!((mip.bicubic(0.5, 0.5)[0] - 7.5).abs() < 1e-12);
+
+        // フットプリントが 0 なら trilinear は bilinear と同じで、十分大きければ平均になる
+        assert_eq!(mip.trilinear(0.4, 0.6, 0.0), mip.bilinear(0, 0.4, 0.6));
+        assert_eq!(mip.trilinear(0.4, 0.6, 10.0)[0], 7.5);
+        assert!((mip.ewa(0.5, 0.5, [2.0, 0.0], [0.0, 2.0])[0] - 7.5).abs() < 1e-12);
+
+        // 縦縞の画像では、横に長いフットプリントだけが縞を平均する
+        let stripes = Rgba32FImage::from_fn(4, 4, |x, _| Rgba([(x % 2) as f32, 0.0, 0.0, 1.0]));
+        let mip = MipMap::new(&stripes, WrapMode::Repeat);
+        let a = mip.ewa(0.125, 0.5, [0.5, 0.0], [0.0, 0.01]);
+        let b = mip.ewa(0.125, 0.5, [0.01, 0.0], [0.0, 0.5]);
+        assert!(a[0] > 0.2);
+        assert!(b[0] < 0.05);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    onb::Onb,
+    rtweekend::{Point3, Ray, Vec3},
+    texture::Texture,
+};
+use std::sync::Arc;
+
+// レイの差分がないときに有限差分に使う (u, v) の刻み幅
+const BUMP_DELTA: f64 = 0.0005;
+
+// 面の外向きの法線。シェーディング法線は外向きで計算してから、レイの来た側へ戻す
+fn outward_normal(rec: &HitRecord) -> Vec3 {
+    if rec.front_face {
+        rec.geometric_normal
+    } else {
+        -rec.geometric_normal
+    }
+}
+
+fn apply_shading_normal(r: &Ray, rec: &mut HitRecord, outward_shading: Vec3) {
+    let shading_normal = if rec.front_face {
+        outward_shading
+    } else {
+        -outward_shading
+    };
+    rec.set_shading_normal(r, shading_normal);
+}
+
+// スカラーのテクスチャ (RGB の平均) を高さとして法線を摂動するバンプマップ
+pub struct BumpMap {
+    obj_ptr: Arc<dyn Hittable>,
+    height: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMap {
+    pub fn new(obj_ptr: Arc<dyn Hittable>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            obj_ptr,
+            height,
+            scale,
+        }
+    }
+
+    // 交点から (u, v) を du, dv だけずらした位置の高さ。フットプリントは交点のものを使う
+    fn height_at(&self, rec: &HitRecord, du: f64, dv: f64) -> f64 {
+        let mut at = *rec;
+        at.u += du;
+        at.v += dv;
+        at.p += du * rec.dpdu + dv * rec.dpdv;
+        let c = self.height.value_at(&at);
+        self.scale * (c.e[0] + c.e[1] + c.e[2]) / 3.0
+    }
+
+    // 高さで変位させた面の接線から外向きのシェーディング法線を求める
+    fn bumped_normal(&self, rec: &HitRecord) -> Option<Vec3> {
+        let n = outward_normal(rec);
+        let h = self.height_at(rec, 0.0, 0.0);
+        // 刻み幅はピクセルのフットプリントに合わせる
+        let step = |dx: f64, dy: f64| match 0.5 * (dx.abs() + dy.abs()) {
+            d if d > 0.0 => d,
+            _ => BUMP_DELTA,
+        };
+        let du = step(rec.dudx, rec.dudy);
+        let dv = step(rec.dvdx, rec.dvdy);
+        let h_u = self.height_at(rec, du, 0.0);
+        let h_v = self.height_at(rec, 0.0, dv);
+
+        let dpdu = rec.dpdu + (h_u - h) / du * n;
+        let dpdv = rec.dpdv + (h_v - h) / dv * n;
+        let bumped = dpdu.cross(dpdv);
+        if bumped.length_squared() <= 1e-16 {
+            return None;
+        }
+        let bumped = bumped.unit();
+
+        Some(if bumped.dot(n) < 0.0 { -bumped } else { bumped })
+    }
+}
+
+impl Hittable for BumpMap {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.obj_ptr.hit(r, ray_t, rec) {
+            return false;
+        }
+
+        // 接線を持たない交点 (ボリュームなど) はそのまま
+        if let Some(n) = self.bumped_normal(rec) {
+            apply_shading_normal(r, rec, n);
+        }
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.obj_ptr.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.obj_ptr.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.obj_ptr.random(origin)
+    }
+}
+
+// 接空間の法線マップ。RGB を [-1, 1] に戻し、(dpdu, n × dpdu, n) の基底で解釈する。
+// テクスチャは ImageTexture::new_linear でガンマ補正せずに読み込んだものを使う
+pub struct NormalMap {
+    obj_ptr: Arc<dyn Hittable>,
+    normals: Arc<dyn Texture>,
+    strength: f64,
+}
+
+impl NormalMap {
+    pub fn new(obj_ptr: Arc<dyn Hittable>, normals: Arc<dyn Texture>) -> Self {
+        Self::new_with_strength(obj_ptr, normals, 1.0)
+    }
+
+    // strength で接空間の xy 成分を拡大・縮小する
+    pub fn new_with_strength(
+        obj_ptr: Arc<dyn Hittable>,
+        normals: Arc<dyn Texture>,
+        strength: f64,
+    ) -> Self {
+        Self {
+            obj_ptr,
+            normals,
+            strength,
+        }
+    }
+
+    fn mapped_normal(&self, rec: &HitRecord) -> Vec3 {
+        let c = self.normals.value_at(rec);
+        let local = Vec3 {
+            e: [
+                self.strength * (2.0 * c.e[0] - 1.0),
+                self.strength * (2.0 * c.e[1] - 1.0),
+                (2.0 * c.e[2] - 1.0).max(0.0),
+            ],
+        };
+        let uvw = Onb::build_from_w_and_tangent(outward_normal(rec), rec.dpdu);
+
+        uvw.transform_vec3(local)
+    }
+}
+
+impl Hittable for NormalMap {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        if !self.obj_ptr.hit(r, ray_t, rec) {
+            return false;
+        }
+
+        let n = self.mapped_normal(rec);
+        if n.length_squared() > 1e-16 {
+            apply_shading_normal(r, rec, n);
+        }
+
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.obj_ptr.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.obj_ptr.pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Vec3) -> Vec3 {
+        self.obj_ptr.random(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color, material::EmptyMaterial, point3, quad::Quad, rtweekend::Color, texture::SolidColor,
+        vec3,
+    };
+
+    // 高さが u に比例する坂
+    struct Slope;
+
+    impl Texture for Slope {
+        fn value(&self, u: f64, _: f64, _: &Point3) -> Color {
+            color!(u, u, u)
+        }
+    }
+
+    fn unit_quad() -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(
+            point3!(0, 0, 0),
+            vec3!(1, 0, 0),
+            vec3!(0, 1, 0),
+            Arc::new(EmptyMaterial),
+        ))
+    }
+
+    fn hit_from(obj: &dyn Hittable, z: f64) -> (HitRecord, bool) {
+        let mut rec = HitRecord::default();
+        let r = Ray::new(point3!(0.5, 0.5, z), vec3!(0, 0, -z));
+        let hit = obj.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rec);
+        (rec, hit)
+    }
+
+    #[test]
+    fn test_bump_map() {
+        // 高さが一定なら法線は変わらない
+        let flat = BumpMap::new(unit_quad(), Arc::new(SolidColor::new(color!(1, 1, 1))), 1.0);
+        let (rec, hit) = hit_from(&flat, 1.0);
+        assert!(hit);
+        assert!((rec.normal - vec3!(0, 0, 1)).length() < 1e-9);
+
+        // h = 0.5 u の坂の法線は (-0.5, 0, 1) 方向
+        let slope = BumpMap::new(unit_quad(), Arc::new(Slope), 0.5);
+        let (rec, _) = hit_from(&slope, 1.0);
+        assert!((rec.normal - vec3!(-0.5, 0, 1).unit()).length() < 1e-6);
+        assert_eq!(rec.geometric_normal, vec3!(0, 0, 1));
+
+        // 裏から見ると両方の法線が裏返る
+        let (rec, _) = hit_from(&slope, -1.0);
+        assert!((rec.normal - vec3!(0.5, 0, -1).unit()).length() < 1e-6);
+        assert_eq!(rec.geometric_normal, vec3!(0, 0, -1));
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let flat = NormalMap::new(unit_quad(), Arc::new(SolidColor::new(color!(0.5, 0.5, 1))));
+        let (rec, _) = hit_from(&flat, 1.0);
+        assert!((rec.normal - vec3!(0, 0, 1)).length() < 1e-9);
+
+        // 接空間の +x は dpdu の方向
+        let tilted = NormalMap::new(unit_quad(), Arc::new(SolidColor::new(color!(1, 0.5, 1))));
+        let (rec, _) = hit_from(&tilted, 1.0);
+        assert!((rec.normal - vec3!(1, 0, 1).unit()).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_shading_normal_bent_towards_viewer() {
+        let mut rec = HitRecord::default();
+        let r = Ray::new(point3!(0, 0, 1), vec3!(1, 0, -0.1));
+        rec.set_face_normal(&r, &vec3!(0, 0, 1));
+        rec.set_shading_normal(&r, vec3!(1, 0, 0.2));
+
+        assert!((-r.dir.unit()).dot(rec.normal) > 0.0);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-9);
+
+        // 幾何法線とシェーディング法線で表裏が食い違う方向は漏れとして扱う
+        assert!(rec.same_side(&vec3!(0, 0, 1)));
+        assert!(!rec.same_side(&vec3!(-1, 0, 0.05)));
+    }
+}
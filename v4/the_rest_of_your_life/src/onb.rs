@@ -25,6 +25,19 @@ impl Onb {
         Self { axis: [u, v, w] }
     }
 
+    // u 軸を接線 t の方向に揃える。t が法線と平行なら build_from_w と同じ
+    pub fn build_from_w_and_tangent(n: Vec3, t: Vec3) -> Self {
+        let w = n.unit();
+        let t_perp = t - t.dot(w) * w;
+        if t_perp.length_squared() <= 1e-12 * t.length_squared() || t.length_squared() == 0.0 {
+            return Self::build_from_w(n);
+        }
+        let u = t_perp.unit();
+        let v = w.cross(u);
+
+        Self { axis: [u, v, w] }
+    }
+
     pub fn u(&self) -> Vec3 {
         self.axis[0]
     }
@@ -42,6 +55,11 @@ impl Onb {
     pub fn transform_vec3(&self, v: Vec3) -> Vec3 {
         v.e[0] * self[0] + v.e[1] * self[1] + v.e[2] * self[2]
     }
+
+    // ワールド座標のベクトルをこの基底の局所座標に変換する
+    pub fn inverse_transform_vec3(&self, v: Vec3) -> Vec3 {
+        vec3!(v.dot(self[0]), v.dot(self[1]), v.dot(self[2]))
+    }
 }
 
 impl Index<usize> for Onb {
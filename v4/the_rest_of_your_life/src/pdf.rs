@@ -1,8 +1,9 @@
 use crate::{
-    hittable::Hittable,
+    hittable::{HitRecord, Hittable},
+    microfacet::{fresnel_dielectric, gtr1_d, gtr1_sample, TrowbridgeReitz},
     onb::Onb,
-    rtweekend::{random, Point3, Vec3, PI},
-    vec3::{random_cosine_direction, random_unit_vector},
+    rtweekend::{random, Point3, Ray, Vec3, PI},
+    vec3::{random_cosine_direction, random_unit_vector, reflect, refract},
 };
 
 pub trait Pdf: Sync + Send {
@@ -10,6 +11,16 @@ pub trait Pdf: Sync + Send {
     fn generate(&self) -> Vec3;
 }
 
+// 法線まわりの cos 分布の確率密度。拡散面の scattering_pdf に使う
+pub fn cosine_pdf(rec: &HitRecord, scattered: &Ray) -> f64 {
+    let cosine = rec.normal.dot(scattered.dir.unit());
+    if cosine < 0.0 {
+        0.0
+    } else {
+        cosine / PI
+    }
+}
+
 pub struct CosinePdf {
     uvw: Onb,
 }
@@ -49,6 +60,201 @@ impl Pdf for CosinePdf {
     }
 }
 
+// 可視法線分布で微小面をサンプリングして反射させる
+pub struct GgxReflectionPdf {
+    uvw: Onb,
+    wo: Vec3,
+    distrib: TrowbridgeReitz,
+}
+
+impl GgxReflectionPdf {
+    pub fn new(uvw: Onb, wo: Vec3, distrib: TrowbridgeReitz) -> Self {
+        Self { uvw, wo, distrib }
+    }
+
+    // 局所座標での入射方向 wo と反射方向 wi に対する確率密度
+    pub fn value_local(distrib: &TrowbridgeReitz, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.e[2] * wi.e[2] <= 0.0 {
+            return 0.0;
+        }
+        let wm = (wo + wi).unit();
+
+        distrib.d_visible(wo, wm) / (4.0 * wo.dot(wm).abs())
+    }
+}
+
+impl Pdf for GgxReflectionPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wi = self.uvw.inverse_transform_vec3(direction.unit());
+        Self::value_local(&self.distrib, self.wo, wi)
+    }
+
+    fn generate(&self) -> Vec3 {
+        let wm = self.distrib.sample_wm(self.wo, random(), random());
+        self.uvw.transform_vec3(reflect(&-self.wo, &wm))
+    }
+}
+
+// 粗い誘電体の反射と透過をフレネル項の比で選んでサンプリングする (Walter et al. 2007)
+// eta は透過側と入射側の屈折率の比で、wo は常に局所座標の上半球にある
+pub struct RoughDielectricPdf {
+    uvw: Onb,
+    wo: Vec3,
+    eta: f64,
+    distrib: TrowbridgeReitz,
+}
+
+impl RoughDielectricPdf {
+    pub fn new(uvw: Onb, wo: Vec3, eta: f64, distrib: TrowbridgeReitz) -> Self {
+        Self {
+            uvw,
+            wo,
+            eta,
+            distrib,
+        }
+    }
+
+    // 一般化ハーフベクトルを返す。向きが不正な組み合わせでは None
+    pub fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+        let is_reflection = wo.e[2] * wi.e[2] > 0.0;
+        let etap = if is_reflection { 1.0 } else { eta };
+        let wm = wi * etap + wo;
+        if wi.e[2] == 0.0 || wo.e[2] == 0.0 || wm.length_squared() == 0.0 {
+            return None;
+        }
+        let wm = wm.unit();
+        let wm = if wm.e[2] < 0.0 { -wm } else { wm };
+
+        if wm.dot(wi) * wi.e[2] < 0.0 || wm.dot(wo) * wo.e[2] < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+
+    pub fn generate_local(distrib: &TrowbridgeReitz, wo: Vec3, eta: f64) -> Vec3 {
+        let wm = distrib.sample_wm(wo, random(), random());
+        let r = fresnel_dielectric(wo.dot(wm), eta);
+        if random() < r {
+            reflect(&-wo, &wm)
+        } else {
+            refract(&-wo, &wm, 1.0 / eta)
+        }
+    }
+
+    // 粗いと反射側の微小面から下半球へ、透過側から上半球へ向かうこともあるので両方の密度を足す
+    pub fn value_local(distrib: &TrowbridgeReitz, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        let mut pdf = 0.0;
+
+        let wm = (wo + wi).unit();
+        if wm.e[2] > 0.0 {
+            let r = fresnel_dielectric(wo.dot(wm), eta);
+            pdf += distrib.d_visible(wo, wm) / (4.0 * wo.dot(wm)) * r;
+        }
+
+        let wm = (wo + eta * wi).unit();
+        let wm = if wm.e[2] < 0.0 { -wm } else { wm };
+        if wo.dot(wm) > 0.0 && wi.dot(wm) < 0.0 {
+            let r = fresnel_dielectric(wo.dot(wm), eta);
+            let denom = (wi.dot(wm) + wo.dot(wm) / eta).powi(2);
+            let dwm_dwi = wi.dot(wm).abs() / denom;
+            pdf += distrib.d_visible(wo, wm) * dwm_dwi * (1.0 - r);
+        }
+
+        pdf
+    }
+}
+
+impl Pdf for RoughDielectricPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let wi = self.uvw.inverse_transform_vec3(direction.unit());
+        Self::value_local(&self.distrib, self.wo, wi, self.eta)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw
+            .transform_vec3(Self::generate_local(&self.distrib, self.wo, self.eta))
+    }
+}
+
+// Principled BSDF のローブ (拡散, 鏡面, クリアコート, 透過) を確率 probs で選んでサンプリングする
+pub struct PrincipledPdf {
+    uvw: Onb,
+    wo: Vec3,
+    probs: [f64; 4],
+    specular: TrowbridgeReitz,
+    clearcoat_alpha: f64,
+    eta: f64,
+}
+
+impl PrincipledPdf {
+    pub fn new(
+        uvw: Onb,
+        wo: Vec3,
+        probs: [f64; 4],
+        specular: TrowbridgeReitz,
+        clearcoat_alpha: f64,
+        eta: f64,
+    ) -> Self {
+        Self {
+            uvw,
+            wo,
+            probs,
+            specular,
+            clearcoat_alpha,
+            eta,
+        }
+    }
+
+    pub fn value_local(&self, wi: Vec3) -> f64 {
+        let wo = self.wo;
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = self.probs;
+        let mut pdf = 0.0;
+
+        if wi.e[2] > 0.0 {
+            pdf += p_diffuse * wi.e[2] / PI;
+        }
+        if p_specular > 0.0 {
+            pdf += p_specular * GgxReflectionPdf::value_local(&self.specular, wo, wi);
+        }
+        if p_clearcoat > 0.0 && wo.e[2] * wi.e[2] > 0.0 {
+            let wm = (wo + wi).unit();
+            pdf +=
+                p_clearcoat * gtr1_d(wm.e[2], self.clearcoat_alpha) * wm.e[2] / (4.0 * wo.dot(wm));
+        }
+        if p_transmission > 0.0 {
+            pdf +=
+                p_transmission * RoughDielectricPdf::value_local(&self.specular, wo, wi, self.eta);
+        }
+
+        pdf
+    }
+}
+
+impl Pdf for PrincipledPdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.value_local(self.uvw.inverse_transform_vec3(direction.unit()))
+    }
+
+    fn generate(&self) -> Vec3 {
+        let [p_diffuse, p_specular, p_clearcoat, _] = self.probs;
+        let u = random();
+
+        let wi = if u < p_diffuse {
+            random_cosine_direction()
+        } else if u < p_diffuse + p_specular {
+            let wm = self.specular.sample_wm(self.wo, random(), random());
+            reflect(&-self.wo, &wm)
+        } else if u < p_diffuse + p_specular + p_clearcoat {
+            let wm = gtr1_sample(self.clearcoat_alpha, random(), random());
+            reflect(&-self.wo, &wm)
+        } else {
+            RoughDielectricPdf::generate_local(&self.specular, self.wo, self.eta)
+        };
+
+        self.uvw.transform_vec3(wi)
+    }
+}
+
 unsafe impl Send for HittablePdf {}
 unsafe impl Sync for HittablePdf {}
 
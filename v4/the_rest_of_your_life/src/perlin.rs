@@ -66,6 +66,51 @@ impl Perlin {
         accum.abs()
     }
 
+    // 符号付きの fBm。振幅の合計で割って [-1, 1] 程度に揃える
+    pub fn fbm(&self, p: &Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut norm = 0.0;
+        let mut temp_p = *p;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp_p);
+            norm += weight;
+            weight *= gain;
+            temp_p *= lacunarity;
+        }
+
+        if norm > 0.0 {
+            accum / norm
+        } else {
+            0.0
+        }
+    }
+
+    // Musgrave のリッジマルチフラクタル。前のオクターブの尾根が高いところほど細部を足す
+    pub fn ridged(&self, p: &Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let mut accum = 0.0;
+        let mut norm = 0.0;
+        let mut temp_p = *p;
+        let mut amplitude = 1.0;
+        let mut weight = 1.0;
+
+        for _ in 0..octaves {
+            let signal = (1.0 - self.noise(&temp_p).abs()).powi(2) * weight;
+            accum += amplitude * signal;
+            norm += amplitude;
+            weight = (2.0 * signal).clamp(0.0, 1.0);
+            amplitude *= gain;
+            temp_p *= lacunarity;
+        }
+
+        if norm > 0.0 {
+            accum / norm
+        } else {
+            0.0
+        }
+    }
+
     fn perlin_generate_perm() -> Vec<i32> {
         let mut p = (0..Self::POINT_COUNT as i32).collect();
         Self::permute(&mut p);
@@ -0,0 +1,271 @@
+use crate::{
+    color,
+    hittable::HitRecord,
+    material::{Material, RoughDielectric, ScatterRecord},
+    microfacet::{gtr1_d, schlick_weight, TrowbridgeReitz},
+    onb::Onb,
+    pdf::PrincipledPdf,
+    rtweekend::{Color, Ray, Vec3, PI},
+    texture::{SolidColor, Texture},
+    vec3::luminance,
+};
+use std::sync::Arc;
+
+// Disney の Principled BSDF。パラメータは全てテクスチャで指定でき、スカラー値は輝度を使う
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub anisotropic: Arc<dyn Texture>,
+    pub ref_idx: f64,
+}
+
+// 交点で評価したパラメータ
+struct PrincipledParams {
+    base_color: Color,
+    roughness: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    specular0: Color,
+    specular: TrowbridgeReitz,
+    clearcoat_alpha: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    transmission_weight: f64,
+    eta: f64,
+}
+
+impl Principled {
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            anisotropic: constant(0.0),
+            ref_idx: 1.5,
+        }
+    }
+
+    fn params(&self, rec: &HitRecord) -> PrincipledParams {
+        let scalar = |tex: &Arc<dyn Texture>| luminance(tex.value_at(rec));
+
+        let base_color = self.base_color.value_at(rec);
+        let metallic = scalar(&self.metallic).clamp(0.0, 1.0);
+        let roughness = scalar(&self.roughness).clamp(0.0, 1.0);
+        let transmission = scalar(&self.transmission).clamp(0.0, 1.0);
+
+        let tint = tint_color(base_color);
+        let specular_tint = scalar(&self.specular_tint);
+        let dielectric0 = scalar(&self.specular)
+            * 0.08
+            * (color!(1, 1, 1) * (1.0 - specular_tint) + tint * specular_tint);
+
+        let aspect = (1.0 - 0.9 * scalar(&self.anisotropic).clamp(0.0, 1.0)).sqrt();
+        let alpha = roughness * roughness;
+
+        PrincipledParams {
+            base_color,
+            roughness,
+            sheen: scalar(&self.sheen),
+            sheen_tint: scalar(&self.sheen_tint),
+            clearcoat: scalar(&self.clearcoat),
+            specular0: dielectric0 * (1.0 - metallic) + base_color * metallic,
+            specular: TrowbridgeReitz::new((alpha / aspect).max(1e-3), (alpha * aspect).max(1e-3)),
+            clearcoat_alpha: 0.1 + (0.001 - 0.1) * scalar(&self.clearcoat_gloss),
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            specular_weight: 1.0 - (1.0 - metallic) * transmission,
+            transmission_weight: (1.0 - metallic) * transmission,
+            eta: if rec.front_face {
+                self.ref_idx
+            } else {
+                1.0 / self.ref_idx
+            },
+        }
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, params: &PrincipledParams) -> PrincipledPdf {
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+
+        let weights = [
+            params.diffuse_weight,
+            params.specular_weight,
+            0.25 * params.clearcoat,
+            params.transmission_weight,
+        ];
+        let total: f64 = weights.iter().sum();
+
+        PrincipledPdf::new(
+            uvw,
+            wo,
+            weights.map(|w| w / total),
+            params.specular,
+            params.clearcoat_alpha,
+            params.eta,
+        )
+    }
+
+    // 局所座標での BSDF の値 (cos 項は含まない)
+    fn bsdf_local(params: &PrincipledParams, wo: Vec3, wi: Vec3) -> Color {
+        let mut f = color!(0, 0, 0);
+
+        if params.transmission_weight > 0.0 {
+            let ft = RoughDielectric::bsdf_local(&params.specular, wo, wi, params.eta);
+            let tint = if wo.e[2] * wi.e[2] > 0.0 {
+                color!(1, 1, 1)
+            } else {
+                params.base_color
+            };
+            f += params.transmission_weight * ft * tint;
+        }
+
+        if wo.e[2] <= 0.0 || wi.e[2] <= 0.0 {
+            return f;
+        }
+        let wh = (wo + wi).unit();
+        let cos_d = wi.dot(wh);
+
+        // 拡散とシーン
+        let fd90 = 0.5 + 2.0 * cos_d * cos_d * params.roughness;
+        let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wi.e[2]))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.e[2]));
+        let sheen_color = color!(1, 1, 1) * (1.0 - params.sheen_tint)
+            + tint_color(params.base_color) * params.sheen_tint;
+        let f_sheen = params.sheen * schlick_weight(cos_d) * sheen_color;
+        f += params.diffuse_weight * (fd / PI * params.base_color + f_sheen);
+
+        // 鏡面反射
+        let fresnel = params.specular0 + (1.0 - params.specular0) * schlick_weight(cos_d);
+        let dg = params.specular.d(wh) * params.specular.g(wo, wi);
+        f += params.specular_weight * dg / (4.0 * wo.e[2] * wi.e[2]) * fresnel;
+
+        // クリアコート
+        if params.clearcoat > 0.0 {
+            let dr = gtr1_d(wh.e[2], params.clearcoat_alpha);
+            let fr = 0.04 + 0.96 * schlick_weight(cos_d);
+            let gr = TrowbridgeReitz::new(0.25, 0.25).g(wo, wi);
+            let fc = 0.25 * params.clearcoat * dr * fr * gr / (4.0 * wo.e[2] * wi.e[2]);
+            f += color!(fc, fc, fc);
+        }
+
+        f
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        let params = self.params(rec);
+        srec.attenuation = color!(1, 1, 1);
+        srec.skip_pdf = false;
+        srec.opt_pdf_ptr = Some(Box::new(self.pdf(r_in, rec, &params)));
+        true
+    }
+
+    fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let params = self.params(rec);
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+
+        self.pdf(r_in, rec, &params).value_local(wi)
+    }
+
+    fn scattering_color(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        _srec: &ScatterRecord,
+        scattered: &Ray,
+    ) -> Color {
+        let params = self.params(rec);
+        let uvw = Onb::build_from_w_and_tangent(rec.normal, rec.dpdu);
+        let wo = uvw.inverse_transform_vec3(-r_in.dir.unit());
+        let wi = uvw.inverse_transform_vec3(scattered.dir.unit());
+
+        Self::bsdf_local(&params, wo, wi) * wi.e[2].abs()
+    }
+}
+
+fn constant(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(color!(value, value, value)))
+}
+
+// 輝度で正規化した色味
+fn tint_color(base_color: Color) -> Color {
+    let lum = luminance(base_color);
+    if lum > 0.0 {
+        base_color / lum
+    } else {
+        color!(1, 1, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{point3, rtweekend::Point3, vec3};
+
+    // 上半球と下半球を格子で積分する
+    fn integrate_sphere(f: impl Fn(Vec3) -> f64) -> f64 {
+        let n = 400;
+        let mut sum = 0.0;
+        for i in 0..n {
+            let theta = (i as f64 + 0.5) / n as f64 * PI;
+            for j in 0..n {
+                let phi = (j as f64 + 0.5) / n as f64 * 2.0 * PI;
+                let w = vec3!(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos()
+                );
+                sum += f(w) * theta.sin();
+            }
+        }
+        sum * (PI / n as f64) * (2.0 * PI / n as f64)
+    }
+
+    #[test]
+    fn test_principled_pdf_and_albedo() {
+        let mut mat = Principled::new(constant(1.0));
+        mat.roughness = constant(0.6);
+        mat.clearcoat = constant(1.0);
+        mat.clearcoat_gloss = constant(0.0);
+        mat.transmission = constant(0.5);
+
+        let r_in = Ray::new(point3!(0, 0, 1), vec3!(0.5, 0, -1));
+        let mut rec = HitRecord::default();
+        rec.normal = vec3!(0, 0, 1);
+        rec.front_face = true;
+
+        let params = mat.params(&rec);
+        let pdf = mat.pdf(&r_in, &rec, &params);
+        let wo = -r_in.dir.unit();
+
+        // 地平線の下へ反射したサンプルの分だけ 1 を下回る
+        let pdf_integral = integrate_sphere(|wi| pdf.value_local(wi));
+        assert!(
+            pdf_integral > 0.9 && pdf_integral <= 1.01,
+            "{}",
+            pdf_integral
+        );
+
+        let albedo = integrate_sphere(|wi| {
+            luminance(Principled::bsdf_local(&params, wo, wi)) * wi.e[2].abs()
+        });
+        assert!(albedo > 0.5 && albedo <= 1.05, "{}", albedo);
+    }
+}
@@ -0,0 +1,466 @@
+use crate::{
+    color,
+    hittable::HitRecord,
+    perlin::Perlin,
+    rtweekend::{random_int, Color, Point3, Vec3},
+    texture::Texture,
+    vec3,
+    vec3::luminance,
+};
+use std::sync::Arc;
+
+// 値 x (おおよそ [0, 1]) を灰色にする
+fn gray(x: f64) -> Color {
+    color!(x, x, x)
+}
+
+// fBm (フラクタルブラウン運動) のノイズを [0, 1] の灰色で返す
+pub struct FbmTexture {
+    noise: Perlin,
+    scale: f64,
+    octaves: u32,
+    lacunarity: f64,
+    gain: f64,
+}
+
+impl FbmTexture {
+    pub fn new(scale: f64, octaves: u32) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    // オクターブごとの周波数の倍率と振幅の倍率
+    pub fn with_lacunarity(mut self, lacunarity: f64) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn with_gain(mut self, gain: f64) -> Self {
+        self.gain = gain;
+        self
+    }
+}
+
+impl Texture for FbmTexture {
+    fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+        let n = self
+            .noise
+            .fbm(&(self.scale * *p), self.octaves, self.lacunarity, self.gain);
+        gray((0.5 + 0.5 * n).clamp(0.0, 1.0))
+    }
+}
+
+// 尾根状の模様のリッジマルチフラクタル。山脈や稲妻のような筋になる
+pub struct RidgedTexture {
+    noise: Perlin,
+    scale: f64,
+    octaves: u32,
+    lacunarity: f64,
+    gain: f64,
+}
+
+impl RidgedTexture {
+    pub fn new(scale: f64, octaves: u32) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale,
+            octaves,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    pub fn with_lacunarity(mut self, lacunarity: f64) -> Self {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn with_gain(mut self, gain: f64) -> Self {
+        self.gain = gain;
+        self
+    }
+}
+
+impl Texture for RidgedTexture {
+    fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+        let n = self
+            .noise
+            .ridged(&(self.scale * *p), self.octaves, self.lacunarity, self.gain);
+        gray(n.clamp(0.0, 1.0))
+    }
+}
+
+// 格子の各セルに 1 つずつ特徴点を置いたセルラーノイズ
+struct Cells {
+    seed: u64,
+}
+
+// 点 p に最も近い 2 つの特徴点までの距離と、最も近い特徴点のセルのハッシュ
+struct CellSample {
+    f1: f64,
+    f2: f64,
+    id: u64,
+}
+
+impl Cells {
+    fn new() -> Self {
+        let seed = ((random_int(0, i32::MAX) as u64) << 32) | random_int(0, i32::MAX) as u64;
+        Self { seed }
+    }
+
+    fn hash(&self, cell: [i64; 3]) -> u64 {
+        let mut h = self.seed;
+        for c in cell {
+            h = splitmix64(h ^ c as u64);
+        }
+        h
+    }
+
+    fn feature_point(&self, cell: [i64; 3]) -> Point3 {
+        let h = self.hash(cell);
+        let jitter = |shift: u32| ((h >> shift) & 0xffff) as f64 / 65536.0;
+        vec3!(
+            cell[0] as f64 + jitter(0),
+            cell[1] as f64 + jitter(16),
+            cell[2] as f64 + jitter(32)
+        )
+    }
+
+    fn sample(&self, p: &Point3) -> CellSample {
+        let base = p.e.map(|c| c.floor() as i64);
+        let mut result = CellSample {
+            f1: f64::INFINITY,
+            f2: f64::INFINITY,
+            id: 0,
+        };
+
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let cell = [base[0] + di, base[1] + dj, base[2] + dk];
+                    let d = (self.feature_point(cell) - *p).length();
+                    if d < result.f1 {
+                        result.f2 = result.f1;
+                        result.f1 = d;
+                        result.id = self.hash(cell);
+                    } else if d < result.f2 {
+                        result.f2 = d;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Worley ノイズで返す距離の組み合わせ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorleyMode {
+    F1,
+    F2,
+    // セルの境界で 0 になる
+    F2MinusF1,
+}
+
+// 最も近い特徴点までの距離による Worley (セルラー) ノイズ
+pub struct WorleyTexture {
+    cells: Cells,
+    scale: f64,
+    mode: WorleyMode,
+}
+
+impl WorleyTexture {
+    pub fn new(scale: f64, mode: WorleyMode) -> Self {
+        Self {
+            cells: Cells::new(),
+            scale,
+            mode,
+        }
+    }
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+        let s = self.cells.sample(&(self.scale * *p));
+        let d = match self.mode {
+            WorleyMode::F1 => s.f1,
+            WorleyMode::F2 => s.f2,
+            WorleyMode::F2MinusF1 => s.f2 - s.f1,
+        };
+        gray(d.clamp(0.0, 1.0))
+    }
+}
+
+// セルごとに色を塗り分けるボロノイ模様。色はパレットからセルのハッシュで選ぶ
+pub struct VoronoiTexture {
+    cells: Cells,
+    scale: f64,
+    palette: Vec<Color>,
+}
+
+impl VoronoiTexture {
+    // セルごとにランダムな色を付ける
+    pub fn new(scale: f64) -> Self {
+        Self {
+            cells: Cells::new(),
+            scale,
+            palette: Vec::new(),
+        }
+    }
+
+    pub fn new_with_palette(scale: f64, palette: Vec<Color>) -> Self {
+        Self {
+            cells: Cells::new(),
+            scale,
+            palette,
+        }
+    }
+}
+
+impl Texture for VoronoiTexture {
+    fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+        let id = self.cells.sample(&(self.scale * *p)).id;
+        if self.palette.is_empty() {
+            let channel = |shift: u32| ((id >> shift) & 0xff) as f64 / 255.0;
+            color!(channel(0), channel(8), channel(16))
+        } else {
+            self.palette[(id % self.palette.len() as u64) as usize]
+        }
+    }
+}
+
+// y 軸を中心とする年輪。ノイズで輪をゆがめる
+pub struct WoodTexture {
+    noise: Perlin,
+    light: Color,
+    dark: Color,
+    // 単位長さあたりの年輪の数
+    rings: f64,
+    noise_scale: f64,
+    noise_strength: f64,
+}
+
+impl WoodTexture {
+    pub fn new(light: Color, dark: Color, rings: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            light,
+            dark,
+            rings,
+            noise_scale: 1.0,
+            noise_strength: 0.5,
+        }
+    }
+
+    pub fn with_noise(mut self, scale: f64, strength: f64) -> Self {
+        self.noise_scale = scale;
+        self.noise_strength = strength;
+        self
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+        let radius = (p.e[0] * p.e[0] + p.e[2] * p.e[2]).sqrt();
+        let distortion = self.noise_strength * self.noise.noise(&(self.noise_scale * *p));
+        let ring = (self.rings * radius + distortion).rem_euclid(1.0);
+        // 早材から晩材へ徐々に濃くなり、輪の境目で明るさが戻る
+        let t = ring * ring;
+
+        (1.0 - t) * self.light + t * self.dark
+    }
+}
+
+// 軸 axis に沿った縞を乱流でゆがめた大理石
+pub struct MarbleTexture {
+    noise: Perlin,
+    axis: Vec3,
+    frequency: f64,
+    turbulence: f64,
+    depth: i32,
+}
+
+impl MarbleTexture {
+    pub fn new(axis: Vec3, frequency: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            axis: axis.unit(),
+            frequency,
+            turbulence: 10.0,
+            depth: 7,
+        }
+    }
+
+    // 乱流の強さとオクターブ数
+    pub fn with_turbulence(mut self, turbulence: f64, depth: i32) -> Self {
+        self.turbulence = turbulence;
+        self.depth = depth;
+        self
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _: f64, _: f64, p: &Point3) -> Color {
+        let phase =
+            self.frequency * self.axis.dot(*p) + self.turbulence * self.noise.turb(p, self.depth);
+        gray(0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// スカラーのテクスチャ (輝度) をグラデーションの色に写す
+pub struct ColorRamp {
+    input: Arc<dyn Texture>,
+    // 位置の昇順に並べた (位置, 色)
+    stops: Vec<(f64, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(input: Arc<dyn Texture>, mut stops: Vec<(f64, Color)>) -> Self {
+        assert!(!stops.is_empty(), "ColorRamp needs at least one stop");
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { input, stops }
+    }
+
+    pub fn map(&self, x: f64) -> Color {
+        let i = self.stops.partition_point(|&(pos, _)| pos <= x);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+        if i == self.stops.len() {
+            return self.stops[i - 1].1;
+        }
+
+        let (p0, c0) = self.stops[i - 1];
+        let (p1, c1) = self.stops[i];
+        let t = (x - p0) / (p1 - p0);
+        (1.0 - t) * c0 + t * c1
+    }
+}
+
+impl Texture for ColorRamp {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.map(luminance(self.input.value(u, v, p)))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.map(luminance(self.input.value_at(rec)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{point3, texture::SolidColor};
+
+    fn sample_points() -> impl Iterator<Item = Point3> {
+        (0..200).map(|i| {
+            let x = i as f64 * 0.37;
+            point3!(x.sin() * 5.0, x * 0.1, (1.3 * x).cos() * 3.0)
+        })
+    }
+
+    #[test]
+    fn test_noise_textures_in_range() {
+        let textures: Vec<Box<dyn Texture>> = vec![
+            Box::new(FbmTexture::new(2.0, 6)),
+            Box::new(RidgedTexture::new(2.0, 6).with_gain(0.6)),
+            Box::new(WorleyTexture::new(3.0, WorleyMode::F1)),
+            Box::new(WorleyTexture::new(3.0, WorleyMode::F2MinusF1)),
+            Box::new(MarbleTexture::new(vec3!(1, 0, 0), 4.0)),
+        ];
+        for texture in &textures {
+            for p in sample_points() {
+                let c = texture.value(0.0, 0.0, &p);
+                assert!(c.e.iter().all(|&x| (0.0..=1.0).contains(&x)), "{:?}", c);
+                assert_eq!(c, texture.value(0.0, 0.0, &p));
+            }
+        }
+    }
+
+    #[test]
+    fn test_worley() {
+        let cells = Cells::new();
+        for p in sample_points() {
+            let s = cells.sample(&p);
+            assert!(s.f1 <= s.f2);
+            // 特徴点は各セルの中にあるので、最も近い点は隣のセルまでに見つかる
+            assert!(s.f1 < 3f64.sqrt());
+        }
+
+        // 特徴点の上では F1 = 0
+        let feature = cells.feature_point([2, -1, 5]);
+        let s = cells.sample(&feature);
+        assert!(s.f1 < 1e-12);
+        assert_eq!(s.id, cells.hash([2, -1, 5]));
+    }
+
+    #[test]
+    fn test_voronoi_palette() {
+        let palette = vec![color!(1, 0, 0), color!(0, 1, 0), color!(0, 0, 1)];
+        let voronoi = VoronoiTexture::new_with_palette(1.0, palette.clone());
+        for p in sample_points() {
+            assert!(palette.contains(&voronoi.value(0.0, 0.0, &p)));
+        }
+
+        // 同じ特徴点のごく近くでは同じ色
+        let feature = voronoi.cells.feature_point([0, 0, 0]);
+        assert_eq!(
+            voronoi.value(0.0, 0.0, &feature),
+            voronoi.value(0.0, 0.0, &(feature + vec3!(1e-3, 0, 0)))
+        );
+    }
+
+    #[test]
+    fn test_wood_rings() {
+        let wood = WoodTexture::new(color!(0.8, 0.6, 0.4), color!(0.4, 0.2, 0.1), 4.0)
+            .with_noise(1.0, 0.0);
+        // 年輪の内側ほど明るく、1 周期ずらすと同じ色
+        let p = point3!(0.3, 0.7, 0.4);
+        let q = p + vec3!(0.25 * 0.6, 0, 0.25 * 0.8);
+        assert_eq!(
+            wood.value(0.0, 0.0, &point3!(0, 0, 0)),
+            color!(0.8, 0.6, 0.4)
+        );
+        assert!((wood.value(0.0, 0.0, &p) - wood.value(0.0, 0.0, &q)).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_marble_without_turbulence() {
+        let marble = MarbleTexture::new(vec3!(0, 0, 2), 3.0).with_turbulence(0.0, 7);
+        let p = point3!(5, 1, 0.25);
+        let expected = 0.5 * (1.0 + 0.75f64.sin());
+        assert!((marble.value(0.0, 0.0, &p).e[0] - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_color_ramp() {
+        let ramp = |x: f64| {
+            ColorRamp::new(
+                Arc::new(SolidColor::new(color!(x, x, x))),
+                vec![
+                    (1.0, color!(0, 0, 1)),
+                    (0.0, color!(1, 0, 0)),
+                    (0.5, color!(0, 1, 0)),
+                ],
+            )
+        };
+        let p = point3!(0, 0, 0);
+        assert_eq!(ramp(-1.0).value(0.0, 0.0, &p), color!(1, 0, 0));
+        assert_eq!(ramp(0.5).value(0.0, 0.0, &p), color!(0, 1, 0));
+        assert!((ramp(0.75).value(0.0, 0.0, &p) - color!(0, 0.5, 0.5)).length() < 1e-9);
+        assert_eq!(ramp(2.0).value(0.0, 0.0, &p), color!(0, 0, 1));
+    }
+}
@@ -85,8 +85,12 @@ impl Hittable for Quad {
 
         rec.t = t;
         rec.p = intersection;
+        rec.obj_id = self as *const Self as usize;
         rec.mat = Some(std::sync::Arc::as_ptr(&self.mat_ptr));
         rec.set_face_normal(r, &self.normal);
+        rec.dpdu = self.u;
+        rec.dpdv = self.v;
+        rec.compute_differentials(r);
 
         return true;
     }
@@ -1,10 +1,36 @@
-use crate::vec3::{Point3, Vec3};
+use crate::{
+    interior::InteriorStack,
+    vec3::{Point3, Vec3},
+};
+
+// 隣のピクセルに向かうレイ。テクスチャのフットプリントを見積もるのに使う
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferential {
+    pub rx_orig: Point3,
+    pub rx_dir: Vec3,
+    pub ry_orig: Point3,
+    pub ry_dir: Vec3,
+}
+
+impl RayDifferential {
+    // 原点と方向をそれぞれ変換する
+    pub fn map(&self, orig: impl Fn(Point3) -> Point3, dir: impl Fn(Vec3) -> Vec3) -> Self {
+        Self {
+            rx_orig: orig(self.rx_orig),
+            rx_dir: dir(self.rx_dir),
+            ry_orig: orig(self.ry_orig),
+            ry_dir: dir(self.ry_dir),
+        }
+    }
+}
 
 #[derive(Default, Debug, Clone)]
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
     pub time: f64,
+    pub interior: InteriorStack,
+    pub differential: Option<RayDifferential>,
 }
 
 impl Ray {
@@ -13,11 +39,36 @@ impl Ray {
             orig,
             dir,
             time: 0.0,
+            interior: InteriorStack::default(),
+            differential: None,
         }
     }
 
     pub fn new_with_time(orig: Point3, dir: Vec3, time: f64) -> Self {
-        Self { orig, dir, time }
+        Self {
+            orig,
+            dir,
+            time,
+            interior: InteriorStack::default(),
+            differential: None,
+        }
+    }
+
+    // 時刻と内部の媒質を引き継いだ次のレイを返す。
+    // 散乱した方向に対しては差分を求められないので引き継がない
+    pub fn spawn(&self, orig: Point3, dir: Vec3) -> Self {
+        Self {
+            orig,
+            dir,
+            time: self.time,
+            interior: self.interior,
+            differential: None,
+        }
+    }
+
+    pub fn with_differential(mut self, differential: Option<RayDifferential>) -> Self {
+        self.differential = differential;
+        self
     }
 
     pub fn at(&self, t: f64) -> Point3 {
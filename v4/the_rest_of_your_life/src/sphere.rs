@@ -59,13 +59,31 @@ impl Sphere {
         self.center1 + time * self.center_vec
     }
 
-    fn get_sphere_uv(p: &Point3, u: &mut f64, v: &mut f64) {
+    pub(crate) fn get_sphere_uv(p: &Point3, u: &mut f64, v: &mut f64) {
         let theta = (-p.e[1]).acos();
         let phi = f64::atan2(-p.e[2], p.e[0]) + PI;
 
         *u = phi / (2.0 * PI);
         *v = theta / PI;
     }
+
+    // get_sphere_uv の (u, v) に対する偏微分。極では dpdu が 0 になる
+    fn get_sphere_tangents(&self, p: &Point3) -> (Vec3, Vec3) {
+        let sin_theta = (p.e[0] * p.e[0] + p.e[2] * p.e[2]).sqrt();
+        let dpdu = 2.0 * PI * self.radius * vec3!(p.e[2], 0, -p.e[0]);
+        let dpdv = if sin_theta > 0.0 {
+            PI * self.radius
+                * vec3!(
+                    -p.e[1] * p.e[0] / sin_theta,
+                    sin_theta,
+                    -p.e[1] * p.e[2] / sin_theta
+                )
+        } else {
+            vec3!(PI * self.radius, 0, 0)
+        };
+
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
@@ -100,6 +118,9 @@ impl Hittable for Sphere {
         let outward_normal = (rec.p - center) / self.radius;
         rec.set_face_normal(r, &outward_normal);
         Sphere::get_sphere_uv(&outward_normal, &mut rec.u, &mut rec.v);
+        (rec.dpdu, rec.dpdv) = self.get_sphere_tangents(&outward_normal);
+        rec.compute_differentials(r);
+        rec.obj_id = self as *const Self as usize;
         rec.mat = Some(std::sync::Arc::as_ptr(&self.mat_ptr));
 
         true
@@ -155,4 +176,43 @@ mod tests {
             assert_eq!(v_res, v_exp, "Failed v_res for input: '{:?}", p);
         }
     }
+
+    #[test]
+    fn test_get_sphere_tangents() {
+        let sphere = Sphere::new(
+            Point3::default(),
+            2.0,
+            Arc::new(crate::material::Lambertian::new(Arc::new(
+                crate::texture::SolidColor::new(Vec3::default()),
+            ))),
+        );
+        // get_sphere_uv の逆写像
+        let point = |u: f64, v: f64| {
+            let theta = v * PI;
+            let phi = 2.0 * PI * u - PI;
+            vec3!(
+                theta.sin() * phi.cos(),
+                -theta.cos(),
+                -theta.sin() * phi.sin()
+            )
+        };
+        let test_cases = vec![(0.1, 0.3), (0.6, 0.5), (0.9, 0.8)];
+        let h = 1e-6;
+
+        for (u, v) in test_cases {
+            let (dpdu, dpdv) = sphere.get_sphere_tangents(&point(u, v));
+            let dpdu_exp = sphere.radius * (point(u + h, v) - point(u - h, v)) / (2.0 * h);
+            let dpdv_exp = sphere.radius * (point(u, v + h) - point(u, v - h)) / (2.0 * h);
+            assert!(
+                (dpdu - dpdu_exp).length() < 1e-6,
+                "Failed for input: '{:?}",
+                (u, v)
+            );
+            assert!(
+                (dpdv - dpdv_exp).length() < 1e-6,
+                "Failed for input: '{:?}",
+                (u, v)
+            );
+        }
+    }
 }
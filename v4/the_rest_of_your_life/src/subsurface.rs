@@ -0,0 +1,246 @@
+use crate::{
+    aabb::Aabb,
+    color,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{Material, ScatterRecord},
+    microfacet::fresnel_dielectric,
+    rtweekend::{random, Color, Ray},
+    vec3::{random_unit_vector, reflect, refract},
+};
+use std::sync::Arc;
+
+// 閉じた境界の内部をランダムウォークする表面下散乱。
+// 自由行程は RGB のうち一様に選んだチャンネルの消散係数でサンプリングし、
+// 重みは 3 チャンネルの平均の確率密度で割って求める
+pub struct Subsurface {
+    boundary: Arc<dyn Hittable>,
+    interface: Arc<dyn Material>,
+    phase_function: Arc<dyn Material>,
+    sigma_t: Color,
+}
+
+// チャンネルごとの消散係数とアルベド
+#[derive(Clone, Copy)]
+struct RandomWalk {
+    sigma_t: Color,
+    albedo: Color,
+}
+
+impl RandomWalk {
+    fn transmittance(&self, distance: f64) -> Color {
+        Color {
+            e: self.sigma_t.e.map(|s| (-s * distance).exp()),
+        }
+    }
+
+    // 距離 distance で散乱する重み: sigma_s T(d) / p(d)
+    fn scatter_weight(&self, distance: f64) -> Color {
+        let tr = self.transmittance(distance);
+        let pdf = (0..3).map(|c| self.sigma_t.e[c] * tr.e[c]).sum::<f64>() / 3.0;
+
+        self.albedo * self.sigma_t * tr / pdf
+    }
+
+    // 距離 distance を散乱せずに通り抜ける重み: T(d) / P(d)
+    fn escape_weight(&self, distance: f64) -> Color {
+        let tr = self.transmittance(distance);
+        let prob = (tr.e[0] + tr.e[1] + tr.e[2]) / 3.0;
+
+        tr / prob
+    }
+}
+
+impl Subsurface {
+    // 平均自由行程 mean_free_path と散乱アルベド albedo は RGB ごとに指定する
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        ref_idx: f64,
+        albedo: Color,
+        mean_free_path: Color,
+    ) -> Self {
+        let sigma_t = Color {
+            e: mean_free_path.e.map(|d| 1.0 / d),
+        };
+        let walk = RandomWalk { sigma_t, albedo };
+
+        Self {
+            boundary,
+            interface: Arc::new(SubsurfaceInterface { ref_idx, walk }),
+            phase_function: Arc::new(SubsurfacePhase { walk }),
+            sigma_t,
+        }
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, r: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        // rec には他の物体の交点が入っている場合があるので、確定するまで書き込まない
+        let mut boundary_rec = HitRecord::default();
+        if !self.boundary.hit(
+            r,
+            Interval::new(ray_t.min, f64::INFINITY),
+            &mut boundary_rec,
+        ) {
+            return false;
+        }
+
+        // 内部にいるときだけ自由行程をサンプリングする。区間の始まりから測る
+        if !boundary_rec.front_face {
+            let channel = ((3.0 * random()) as usize).min(2);
+            let t = ray_t.min.max(0.0) - random().ln() / self.sigma_t.e[channel] / r.dir.length();
+            if t < boundary_rec.t {
+                if !ray_t.contains(t) {
+                    return false;
+                }
+                *rec = boundary_rec;
+                rec.t = t;
+                rec.p = r.at(t);
+                rec.normal = -r.dir.unit();
+                rec.geometric_normal = rec.normal;
+                rec.front_face = true;
+                rec.mat = Some(Arc::as_ptr(&self.phase_function));
+                return true;
+            }
+        }
+
+        if !ray_t.contains(boundary_rec.t) {
+            return false;
+        }
+        *rec = boundary_rec;
+        rec.mat = Some(Arc::as_ptr(&self.interface));
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+// 境界面。滑らかな誘電体として反射・屈折し、内側から届いた光には通過分の重みを掛ける
+struct SubsurfaceInterface {
+    ref_idx: f64,
+    walk: RandomWalk,
+}
+
+impl Material for SubsurfaceInterface {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = true;
+        srec.opt_pdf_ptr = None;
+        srec.attenuation = if rec.front_face {
+            color!(1, 1, 1)
+        } else {
+            self.walk.escape_weight(rec.t * r_in.dir.length())
+        };
+
+        let eta = if rec.front_face {
+            self.ref_idx
+        } else {
+            1.0 / self.ref_idx
+        };
+        let unit_direction = r_in.dir.unit();
+        let cos_theta = -unit_direction.dot(rec.normal).min(1.0);
+        let direction = if random() < fresnel_dielectric(cos_theta, eta) {
+            reflect(&unit_direction, &rec.normal)
+        } else {
+            refract(&unit_direction, &rec.normal, 1.0 / eta)
+        };
+        srec.skip_pdf_ray = r_in.spawn(rec.p, direction);
+        true
+    }
+}
+
+// 内部の散乱点での等方的な位相関数
+struct SubsurfacePhase {
+    walk: RandomWalk,
+}
+
+impl Material for SubsurfacePhase {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord, srec: &mut ScatterRecord) -> bool {
+        srec.skip_pdf = true;
+        srec.opt_pdf_ptr = None;
+        srec.attenuation = self.walk.scatter_weight(rec.t * r_in.dir.length());
+        srec.skip_pdf_ray = r_in.spawn(rec.p, random_unit_vector());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable_list::HittableList,
+        material::EmptyMaterial,
+        point3,
+        rtweekend::{Point3, Vec3},
+        sphere::Sphere,
+        vec3,
+    };
+
+    #[test]
+    fn test_random_walk_weights() {
+        // 全チャンネルが同じなら散乱の重みはアルベド、通過の重みは 1
+        let walk = RandomWalk {
+            sigma_t: color!(2, 2, 2),
+            albedo: color!(0.9, 0.5, 0.1),
+        };
+        for distance in [0.1, 1.0, 3.0] {
+            let scatter = walk.scatter_weight(distance);
+            let escape = walk.escape_weight(distance);
+            assert!((scatter - walk.albedo).length() < 1e-12);
+            assert!((escape - color!(1, 1, 1)).length() < 1e-12);
+        }
+
+        // 重みの平均を取ると各チャンネルの本来の値に戻る
+        let walk = RandomWalk {
+            sigma_t: color!(1, 4, 10),
+            albedo: color!(1, 1, 1),
+        };
+        let distance = 0.5;
+        let tr = walk.transmittance(distance);
+        let prob = (tr.e[0] + tr.e[1] + tr.e[2]) / 3.0;
+        assert!((walk.escape_weight(distance) * prob - tr).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_subsurface_keeps_closer_hit() {
+        // t = 1 の球を先に記録し、その後ろにある表面下散乱の物体を調べる
+        let sphere = |center: Point3, radius: f64| -> Arc<dyn Hittable> {
+            Arc::new(Sphere::new(center, radius, Arc::new(EmptyMaterial)))
+        };
+        let mut world = HittableList::new();
+        world.add(sphere(point3!(0, 0, 0), 1.0));
+        // 外側から入る物体と、レイの原点を含んで散乱しない物体
+        let far = color!(1e6, 1e6, 1e6);
+        world.add(Arc::new(Subsurface::new(
+            sphere(point3!(0, 0, -5), 1.0),
+            1.4,
+            color!(1, 1, 1),
+            far,
+        )));
+        world.add(Arc::new(Subsurface::new(
+            sphere(point3!(0, 0, 0), 10.0),
+            1.4,
+            color!(1, 1, 1),
+            far,
+        )));
+
+        let r = Ray::new(point3!(0, 0, 2), vec3!(0, 0, -1));
+        let mut rec = HitRecord::default();
+        assert!(world.hit(&r, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert_eq!(rec.t, 1.0);
+
+        // 区間の始まりより手前で散乱することはなく、交点を失わない
+        let walk = Subsurface::new(
+            sphere(point3!(0, 0, 0), 10.0),
+            1.4,
+            color!(1, 1, 1),
+            color!(1e-6, 1e-6, 1e-6),
+        );
+        for _ in 0..100 {
+            let mut rec = HitRecord::default();
+            assert!(walk.hit(&r, Interval::new(0.5, f64::INFINITY), &mut rec));
+            assert!(rec.t >= 0.5);
+        }
+    }
+}
@@ -1,14 +1,32 @@
 use crate::{
     color,
+    hittable::HitRecord,
+    mipmap::{FilterMode, MipMap, WrapMode},
     perlin::Perlin,
     rtweekend::{Color, Point3},
+    utils::read_pfm,
 };
-use image::Rgb32FImage;
-use std::path::Path;
+use image::{DynamicImage, ImageResult, Rgba32FImage};
 use std::sync::Arc;
+use std::{fs::File, io::BufReader, path::Path};
 
 pub trait Texture: Sync + Send {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+
+    // 交点のフットプリントを使って絞り込める場合は上書きする
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
+
+    // 不透明度。切り抜きに使う
+    fn alpha(&self, _u: f64, _v: f64, _p: &Point3) -> f64 {
+        1.0
+    }
+
+    // value_at と同じく、交点の情報を使える場合は上書きする
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        self.alpha(rec.u, rec.v, &rec.p)
+    }
 }
 pub struct SolidColor {
     color_value: Color,
@@ -58,43 +76,192 @@ impl Texture for CheckerTexture {
     }
 }
 
+// (u, v) 空間の市松模様。曲面に沿って u 方向に u_count 個、v 方向に v_count 個のマスを並べる
+pub struct UvCheckerTexture {
+    u_count: f64,
+    v_count: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl UvCheckerTexture {
+    pub fn new(u_count: f64, v_count: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            u_count,
+            v_count,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let u_integer = (self.u_count * u).floor() as i64;
+        let v_integer = (self.v_count * v).floor() as i64;
+
+        if (u_integer + v_integer).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+// 画素はミップマップの最も細かいレベルとしてだけ持つ
 pub struct ImageTexture {
-    data: Rgb32FImage,
+    mipmap: MipMap,
+    filter: FilterMode,
+}
+
+// 画像の画素値をリニアな値に戻す方法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSpace {
+    // sRGB の区分的な伝達関数
+    Srgb,
+    // 法線マップやラフネスマップなど、そのまま使う値
+    Linear,
+    // 単純なべき乗のガンマ
+    Gamma(f64),
+}
+
+impl ColorSpace {
+    pub fn to_linear(self, c: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb => {
+                if c <= 0.04045 {
+                    c / 12.92
+                } else {
+                    ((c + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            ColorSpace::Linear => c,
+            ColorSpace::Gamma(gamma) => c.max(0.0).powf(gamma as f32),
+        }
+    }
 }
 
 impl ImageTexture {
+    // load と同じ色空間で読み込む。失敗すると panic するので、エラーを扱うときは load を使う
     pub fn new(filename: &Path) -> Self {
-        let mut data = image::open(filename)
-            .expect("Failed to load image")
-            .into_rgb32f();
+        Self::load(filename).expect("Failed to load image")
+    }
+
+    // 法線マップなど、色ではないデータをガンマ補正せずに読み込む
+    pub fn new_linear(filename: &Path) -> Self {
+        Self::load_with_color_space(filename, ColorSpace::Linear).expect("Failed to load image")
+    }
+
+    // 浮動小数点の画像 (HDR, EXR, PFM) はリニア、それ以外は sRGB として読み込む
+    pub fn load(filename: &Path) -> ImageResult<Self> {
+        let (data, is_float) = read_image(filename)?;
+        let color_space = if is_float {
+            ColorSpace::Linear
+        } else {
+            ColorSpace::Srgb
+        };
+
+        Ok(Self::from_encoded(data, color_space))
+    }
+
+    pub fn load_with_color_space(filename: &Path, color_space: ColorSpace) -> ImageResult<Self> {
+        let (data, _) = read_image(filename)?;
+
+        Ok(Self::from_encoded(data, color_space))
+    }
+
+    // アルファはリニアな値なので変換しない
+    fn from_encoded(mut data: Rgba32FImage, color_space: ColorSpace) -> Self {
+        if color_space != ColorSpace::Linear {
+            data.pixels_mut().for_each(|pixel| {
+                for c in &mut pixel.0[..3] {
+                    *c = color_space.to_linear(*c);
+                }
+            });
+        }
 
-        data.pixels_mut().for_each(|pixel| {
-            pixel.0 = pixel.0.map(|c| c.powf(2.2));
-        });
+        Self::from_rgba32f(data)
+    }
 
-        Self { data }
+    // リニアな値を持つ画像から作る
+    pub fn from_rgba32f(data: Rgba32FImage) -> Self {
+        Self {
+            mipmap: MipMap::new(&data, WrapMode::Clamp),
+            filter: FilterMode::Nearest,
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.mipmap = self.mipmap.with_wrap(wrap);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    // (u, v) の周りのフットプリントで絞り込んだ RGBA。
+    // duvdx, duvdy は画面の x, y 方向に 1 ピクセル進んだときの (u, v) の変化量
+    pub fn lookup(&self, u: f64, v: f64, duvdx: [f64; 2], duvdy: [f64; 2]) -> [f64; 4] {
+        // 画像は上の行から並んでいるので v を反転する
+        let (s, t) = (u, 1.0 - v);
+        let dst0 = [duvdx[0], -duvdx[1]];
+        let dst1 = [duvdy[0], -duvdy[1]];
+        match self.filter {
+            FilterMode::Nearest => self.mipmap.nearest(s, t),
+            FilterMode::Bilinear => self.mipmap.bilinear(0, s, t),
+            FilterMode::Bicubic => self.mipmap.bicubic(s, t),
+            FilterMode::Trilinear => {
+                let width = 2.0 * dst0.iter().chain(&dst1).fold(0.0f64, |m, d| m.max(d.abs()));
+                self.mipmap.trilinear(s, t, width)
+            }
+            FilterMode::Ewa => self.mipmap.ewa(s, t, dst0, dst1),
+        }
+    }
+
+    pub fn value_with_footprint(&self, u: f64, v: f64, duvdx: [f64; 2], duvdy: [f64; 2]) -> Color {
+        let texel = self.lookup(u, v, duvdx, duvdy);
+
+        color!(texel[0], texel[1], texel[2])
     }
 }
 
 impl Texture for ImageTexture {
     fn value(&self, u: f64, v: f64, _: &Point3) -> Color {
-        let u = u.clamp(0.0, 1.0);
-        let v = 1.0 - v.clamp(0.0, 1.0);
+        self.value_with_footprint(u, v, [0.0; 2], [0.0; 2])
+    }
 
-        let mut i = (u * self.data.width() as f64) as u32;
-        let mut j = (v * self.data.height() as f64) as u32;
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value_with_footprint(rec.u, rec.v, [rec.dudx, rec.dvdx], [rec.dudy, rec.dvdy])
+    }
 
-        if i >= self.data.width() {
-            i = self.data.width() - 1;
-        }
-        if j >= self.data.height() {
-            j = self.data.height() - 1;
-        }
+    fn alpha(&self, u: f64, v: f64, _: &Point3) -> f64 {
+        self.lookup(u, v, [0.0; 2], [0.0; 2])[3]
+    }
 
-        let pixel = *self.data.get_pixel(i, j);
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        self.lookup(rec.u, rec.v, [rec.dudx, rec.dvdx], [rec.dudy, rec.dvdy])[3]
+    }
+}
 
-        color!(pixel[0], pixel[1], pixel[2])
+// 画像を読み込み、浮動小数点の形式だったかどうかも返す。PFM は image が対応していないので自前で読む
+fn read_image(filename: &Path) -> ImageResult<(Rgba32FImage, bool)> {
+    let is_pfm = filename
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pfm"));
+    if is_pfm {
+        let reader = BufReader::new(File::open(filename)?);
+        return Ok((read_pfm(reader)?, true));
     }
+
+    let image = image::open(filename)?;
+    let is_float = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+
+    Ok((image.into_rgba32f(), is_float))
 }
 
 pub struct NoiseTexture {
@@ -114,3 +281,94 @@ impl Texture for NoiseTexture {
         color!(0.5, 0.5, 0.5) * (1.0 + (self.scale * p.e[2] + 10.0 * self.noise.turb(p, 7)).sin())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point3;
+    use std::io::{self, Cursor, Write};
+
+    #[test]
+    fn test_uv_checker() {
+        let checker = UvCheckerTexture::new(
+            4.0,
+            2.0,
+            Arc::new(SolidColor::new(color!(1, 1, 1))),
+            Arc::new(SolidColor::new(color!(0, 0, 0))),
+        );
+        let p = point3!(0, 0, 0);
+        assert_eq!(checker.value(0.1, 0.1, &p), color!(1, 1, 1));
+        assert_eq!(checker.value(0.3, 0.1, &p), color!(0, 0, 0));
+        assert_eq!(checker.value(0.3, 0.6, &p), color!(1, 1, 1));
+        // 範囲外でも模様が続く
+        assert_eq!(checker.value(-0.1, 0.1, &p), color!(0, 0, 0));
+    }
+
+    #[test]
+    fn test_color_space() {
+        let srgb = ColorSpace::Srgb;
+        assert_eq!(srgb.to_linear(0.0), 0.0);
+        assert!((srgb.to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb.to_linear(0.5) - 0.214).abs() < 1e-3);
+        assert!((srgb.to_linear(0.02) - 0.02 / 12.92).abs() < 1e-9);
+        assert_eq!(ColorSpace::Linear.to_linear(0.5), 0.5);
+        assert!((ColorSpace::Gamma(2.0).to_linear(0.5) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_load_pfm() {
+        // 2x1 のカラー PFM (リトルエンディアン)
+        let mut bytes = b"PF\n2 1\n-1.0\n".to_vec();
+        for c in [0.5f32, 1.0, 2.0, 4.0, 0.0, 0.25] {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(format!("texture_test_{}.pfm", std::process::id()));
+        File::create(&path).unwrap().write_all(&bytes).unwrap();
+
+        // 浮動小数点の画像は色空間を変換しない
+        let texture = ImageTexture::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let p = point3!(0, 0, 0);
+        assert_eq!(texture.value(0.25, 0.5, &p), color!(0.5, 1, 2));
+        assert_eq!(texture.value(0.75, 0.5, &p), color!(4, 0, 0.25));
+        assert_eq!(texture.alpha(0.25, 0.5, &p), 1.0);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let missing = std::env::temp_dir().join("texture_test_missing.png");
+        assert!(ImageTexture::load(&missing).is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("texture_test_{}_bad.pfm", std::process::id()));
+        File::create(&path)
+            .unwrap()
+            .write_all(b"P6\n1 1\n")
+            .unwrap();
+        let result = ImageTexture::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_pfm_header_errors() {
+        let invalid = |header: &[u8]| {
+            let err = read_pfm(Cursor::new(header.to_vec())).unwrap_err();
+            err.kind() == io::ErrorKind::InvalidData
+        };
+        assert!(invalid(b"PF\n0 1\n-1.0\n"));
+        assert!(invalid(b"PF\n1 0\n-1.0\n"));
+        assert!(invalid(b"PF\n-1 1\n-1.0\n"));
+        assert!(invalid(b"PF\n1 1\nscale\n"));
+        // 大きさの計算があふれる
+        assert!(invalid(b"PF\n4294967295 4294967295\n-1.0\n"));
+
+        // データが足りない。ヘッダーの大きさ分のバッファは先に確保しない
+        let truncated = |bytes: &[u8]| {
+            let err = read_pfm(Cursor::new(bytes.to_vec())).unwrap_err();
+            err.kind() == io::ErrorKind::UnexpectedEof
+        };
+        assert!(truncated(b"Pf\n2 2\n-1.0\n\0\0\0\0"));
+        assert!(truncated(b"PF\n100000 100000\n-1.0\n"));
+    }
+}
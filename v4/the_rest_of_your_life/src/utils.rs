@@ -1,4 +1,9 @@
-use crate::vec3::Color;
+use crate::{film::AlphaMode, vec3::Color};
+use image::{ImageResult, Rgba, Rgba32FImage, RgbaImage};
+use std::{
+    io::{self, BufRead, Read},
+    path::Path,
+};
 
 pub fn write_ppm(pixels: Vec<Color>, image_width: usize, image_height: usize) {
     println!("P3\n{} {}\n255", image_width, image_height);
@@ -10,3 +15,136 @@ pub fn write_ppm(pixels: Vec<Color>, image_width: usize, image_height: usize) {
         println!("{} {} {}", ir, ig, ib);
     }
 }
+
+// color はアルファ乗算済みとして受け取る。PNG はガンマ補正した値に対してアルファを扱う
+pub fn write_png<P: AsRef<Path>>(
+    pixels: &[Color],
+    alpha: &[f64],
+    image_width: usize,
+    image_height: usize,
+    mode: AlphaMode,
+    path: P,
+) -> ImageResult<()> {
+    let mut buf = Vec::with_capacity(image_width * image_height * 4);
+    for (pixel_color, &a) in pixels.iter().zip(alpha) {
+        let a = a.clamp(0.0, 1.0);
+        let encoded = unpremultiply(*pixel_color, a).e.map(|x| x.max(0.0).sqrt());
+        let encoded = match mode {
+            AlphaMode::Straight => encoded,
+            AlphaMode::Premultiplied => encoded.map(|x| x * a),
+        };
+        buf.extend(encoded.map(|x| (256.0 * x.clamp(0.0, 0.999)) as u8));
+        buf.push((256.0 * a).clamp(0.0, 255.0) as u8);
+    }
+
+    RgbaImage::from_raw(image_width as u32, image_height as u32, buf)
+        .expect("pixel buffer size mismatch")
+        .save(path)
+}
+
+// EXR にはガンマ補正せずにリニアな値を書き出す
+pub fn write_exr<P: AsRef<Path>>(
+    pixels: &[Color],
+    alpha: &[f64],
+    image_width: usize,
+    image_height: usize,
+    mode: AlphaMode,
+    path: P,
+) -> ImageResult<()> {
+    let mut buf = Vec::with_capacity(image_width * image_height * 4);
+    for (pixel_color, &a) in pixels.iter().zip(alpha) {
+        let color = match mode {
+            AlphaMode::Straight => unpremultiply(*pixel_color, a),
+            AlphaMode::Premultiplied => *pixel_color,
+        };
+        buf.extend(color.e.map(|x| x as f32));
+        buf.push(a as f32);
+    }
+
+    Rgba32FImage::from_raw(image_width as u32, image_height as u32, buf)
+        .expect("pixel buffer size mismatch")
+        .save(path)
+}
+
+fn unpremultiply(color: Color, alpha: f64) -> Color {
+    if alpha > 0.0 {
+        color / alpha
+    } else {
+        color
+    }
+}
+
+// PFM (Portable Float Map) を読み込む。"PF" はカラー、"Pf" はグレースケールで、
+// スケールが負ならリトルエンディアン。行は下から順に並んでいる
+pub fn read_pfm<R: BufRead>(mut reader: R) -> io::Result<Rgba32FImage> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("PFM: {}", msg));
+
+    let channels = match read_token(&mut reader)?.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("bad magic number")),
+    };
+    // 幅と高さは 1 以上の整数
+    let mut size = |name: &str| -> io::Result<u32> {
+        match read_token(&mut reader)?.parse::<u32>() {
+            Ok(0) | Err(_) => Err(invalid(&format!("bad {}", name))),
+            Ok(n) => Ok(n),
+        }
+    };
+    let width = size("width")?;
+    let height = size("height")?;
+    let scale: f64 = read_token(&mut reader)?
+        .parse()
+        .map_err(|_| invalid("bad scale"))?;
+    let little_endian = scale < 0.0;
+
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(channels * 4))
+        .ok_or_else(|| invalid("image too large"))?;
+    // ヘッダーの大きさを信用して先に確保せず、実際に読めた分だけ増やす
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "PFM: truncated pixel data",
+        ));
+    }
+    let values: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|b| {
+            let b = b.try_into().unwrap();
+            if little_endian {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            }
+        })
+        .collect();
+
+    Ok(Rgba32FImage::from_fn(width, height, |x, y| {
+        let k = ((height - 1 - y) as usize * width as usize + x as usize) * channels;
+        if channels == 3 {
+            Rgba([values[k], values[k + 1], values[k + 2], 1.0])
+        } else {
+            Rgba([values[k], values[k], values[k], 1.0])
+        }
+    }))
+}
+
+// 空白で区切られたヘッダーの語を 1 つ読む。直後の空白 1 文字も読み捨てる
+fn read_token<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut token = String::new();
+    let mut byte = [0u8];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0].is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            return Ok(token);
+        }
+        token.push(byte[0] as char);
+    }
+}
@@ -94,6 +94,11 @@ pub fn random_to_sphere(radius: f64, distance_squared: f64) -> Vec3 {
     vec3!(x, y, z)
 }
 
+// 線形 sRGB の相対輝度
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.e[0] + 0.7152 * c.e[1] + 0.0722 * c.e[2]
+}
+
 #[inline]
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     *v - 2.0 * v.dot(*n) * (*n)